@@ -20,7 +20,7 @@ fn put(f: &mut std::fmt::Formatter, s: String) -> std::fmt::Result {
 fn dfs(
     f: &mut std::fmt::Formatter,
     g: &HashMap<usize, HashMap<Option<u8>, HashSet<usize>>>,
-    nodes: &HashMap<usize, (HashSet<usize>, HashSet<usize>)>,
+    nodes: &HashMap<usize, (HashSet<usize>, HashSet<usize>, bool)>,
     used: &mut HashSet<usize>,
     n: usize,
 ) -> std::fmt::Result {
@@ -28,12 +28,16 @@ fn dfs(
         return Ok(());
     }
     used.insert(n);
-    let default: (HashSet<usize>, HashSet<usize>) = (HashSet::new(), HashSet::new());
-    let (h, t) = nodes.get(&n).unwrap_or(&default);
-    if h.is_empty() && t.is_empty() {
+    let default: (HashSet<usize>, HashSet<usize>, bool) = (HashSet::new(), HashSet::new(), false);
+    let (h, t, a) = nodes.get(&n).unwrap_or(&default);
+    let shape = if *a { "doublecircle" } else { "circle" };
+    if h.is_empty() && t.is_empty() && !a {
         put(f, format!("  {n} [shape=\"point\"];\n"))?;
     } else {
-        put(f, format!("  {n} [shape=\"circle\", fontsize=10, label=\""))?;
+        put(
+            f,
+            format!("  {n} [shape=\"{shape}\", fontsize=10, label=\""),
+        )?;
         for g in h {
             put(f, format!("A{g} "))?;
         }
@@ -61,21 +65,17 @@ where
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let mut g: HashMap<usize, HashMap<Option<u8>, HashSet<usize>>> = HashMap::new();
         for (a, b, c) in self.0.edges() {
-            g.entry(a)
-                .or_insert(HashMap::new())
-                .entry(c)
-                .or_insert(HashSet::new())
-                .insert(b);
+            g.entry(a).or_default().entry(c).or_default().insert(b);
         }
         write!(f, "https://dreampuf.github.io/GraphvizOnline/#digraph")?;
-        put(f, format!("{{\n  rankdir=LR;\n"))?;
+        put(f, String::from("{\n  rankdir=LR;\n"))?;
         dfs(
             f,
             &g,
-            &&HashMap::from_iter(self.0.nodes().enumerate()),
+            &HashMap::from_iter(self.0.nodes().enumerate()),
             &mut HashSet::new(),
             0,
         )?;
-        put(f, format!("}}\n"))
+        put(f, String::from("}\n"))
     }
 }
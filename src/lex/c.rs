@@ -0,0 +1,265 @@
+/*
+ * C code generation
+ *
+ * - C99 header with token kinds and scanner state
+ * - source with byte class table and dense transition table over classes
+ * - token kinds are prefixed with upper-cased `name`, functions and types with `name`
+ * - `<name>_lex` implements the same longest match as `Scanner::longest_at`
+ */
+
+const C_LEX: &str = r#"
+void NAME_init(struct NAME_state *st, const char *s, size_t len)
+{
+    st->begin = s;
+    st->pos = s;
+    st->end = s + len;
+    st->text = s;
+    st->len = 0;
+}
+
+int NAME_lex(struct NAME_state *st)
+{
+    const unsigned char *begin = (const unsigned char *)st->begin;
+    const unsigned char *p = (const unsigned char *)st->pos;
+    const unsigned char *end = (const unsigned char *)st->end;
+    int state, look, token = PREFIX_ERROR;
+    size_t len = 0, i;
+    if (p == end)
+        return PREFIX_EOF;
+    state = NAME_start[p == begin ? 0 : NAME_look[NAME_class[p[-1]]]];
+    for (i = 0; p + i != end; i++) {
+        state = NAME_next[state][NAME_class[p[i]]];
+        if (state < 0)
+            break;
+        look = p + i + 1 == end ? 0 : NAME_look[NAME_class[p[i + 1]]];
+        if (NAME_accept[state][look] != PREFIX_ERROR) {
+            token = NAME_accept[state][look];
+            len = i + 1;
+        }
+    }
+    st->text = st->pos;
+    st->len = len;
+    st->pos += len;
+    return token;
+}
+
+const char *NAME_token_name(int token)
+{
+    if (token < 0 || token >= (int)(sizeof(NAME_names) / sizeof(NAME_names[0])))
+        return "ERROR";
+    return NAME_names[token];
+}
+"#;
+
+/// generated C99 header and source for a compiled lexer
+pub struct CCode<'a, K> {
+    scanner: &'a Scanner<K>,
+    /// prefix for all generated names, also the header file name
+    name: &'a str,
+}
+
+impl<'a, K: std::fmt::Display> CCode<'a, K> {
+    /// fails for a scanner without tables (see re::Engine::LazyDFA), for a `name`,
+    /// that is not an identifier, and for token kinds, that are not identifiers
+    /// or clash with ERROR, EOF and the include guard `<PREFIX>_H`
+    pub fn new(scanner: &'a Scanner<K>, name: &'a str) -> Result<CCode<'a, K>> {
+        if scanner.lazy.is_some() {
+            return Err(Error::Lazy);
+        }
+        if !is_ident(name) {
+            return Err(Error::Name(String::from(name)));
+        }
+        match scanner
+            .kind_names()
+            .into_iter()
+            .find(|k| !is_ident(k) || ["ERROR", "EOF", "H"].contains(&k.as_str()))
+        {
+            Some(k) => Err(Error::Kind(k)),
+            None => Ok(CCode { scanner, name }),
+        }
+    }
+
+    fn prefix(&self) -> String {
+        self.name.to_ascii_uppercase()
+    }
+
+    pub fn header(&self) -> String {
+        let (name, prefix) = (self.name, self.prefix());
+        let mut s = String::from("/* generated by rcclex, do not edit */\n\n");
+        s += &format!("#ifndef {prefix}_H\n#define {prefix}_H\n\n#include <stddef.h>\n\n");
+        s += &format!("enum {name}_token {{\n    {prefix}_ERROR = -1,\n    {prefix}_EOF = 0,\n");
+        // kind id is its index + 1
+        for k in self.scanner.kind_names() {
+            s += &format!("    {prefix}_{k},\n");
+        }
+        s += "};\n\n";
+        s += &format!("struct {name}_state {{\n    const char *begin, *pos, *end;\n");
+        s += "    /* last token */\n    const char *text;\n    size_t len;\n};\n\n";
+        s += &format!("void {name}_init(struct {name}_state *st, const char *s, size_t len);\n");
+        s += "/* next token kind, EOF at the end of input, ERROR if no rule matches */\n";
+        s += &format!("int {name}_lex(struct {name}_state *st);\n");
+        s += &format!("const char *{name}_token_name(int token);\n\n#endif\n");
+        s
+    }
+
+    pub fn source(&self) -> String {
+        let (name, prefix) = (self.name, self.prefix());
+        let kinds = self.scanner.kind_names();
+        let n = self.scanner.nodes.len();
+        let mut s = format!("/* generated by rcclex, do not edit */\n\n#include \"{name}.h\"\n\n");
+        s += &format!("static const char *const {name}_names[] = {{\n    \"EOF\",\n");
+        for k in kinds.iter() {
+            s += &format!("    \"{k}\",\n");
+        }
+        s += "};\n\n";
+        s += &format!("static const unsigned char {name}_class[256] = {{\n");
+        for row in self.scanner.classes.map.chunks(16) {
+            s += "   ";
+            for c in row {
+                s += &format!(" {c},");
+            }
+            s += "\n";
+        }
+        s += "};\n\n";
+        let classes = self.scanner.classes.count;
+        s += &format!("static const unsigned char {name}_look[{classes}] = {{");
+        for l in self.scanner.classes.look.iter() {
+            s += &format!(" {l},");
+        }
+        s += " };\n\n";
+        s += &format!("static const int {name}_start[{}] = {{", re::LOOKS);
+        for n in self.scanner.start.iter() {
+            s += &format!(" {n},");
+        }
+        s += " };\n\n";
+        s += &format!("static const int {name}_next[{n}][{classes}] = {{\n");
+        for node in self.scanner.nodes.iter() {
+            s += "    {";
+            for n in node {
+                match *n {
+                    re::DEAD => s += "-1, ",
+                    n => s += &format!("{n}, "),
+                }
+            }
+            s += "},\n";
+        }
+        s += "};\n\n";
+        s += &format!("static const int {name}_accept[{n}][{}] = {{\n", re::LOOKS);
+        for a in self.scanner.accept.iter() {
+            s += "    {";
+            for r in a {
+                match r {
+                    Some(r) => s += &format!("{prefix}_{}, ", self.scanner.kinds[*r]),
+                    None => s += &format!("{prefix}_ERROR, "),
+                }
+            }
+            s += "},\n";
+        }
+        s += "};\n";
+        s + &C_LEX.replace("NAME", name).replace("PREFIX", &prefix)
+    }
+}
+
+#[cfg(test)]
+mod test_c {
+    use super::test_codegen::{interpret, INPUTS, SPEC};
+    use super::*;
+    use std::process::Command;
+
+    const MAIN: &str = r#"
+#include <stdio.h>
+#include <string.h>
+#include "rcc_lex.h"
+
+int main(int argc, char **argv)
+{
+    for (int i = 1; i < argc; i++) {
+        struct rcc_lex_state st;
+        int t;
+        rcc_lex_init(&st, argv[i], strlen(argv[i]));
+        while ((t = rcc_lex_lex(&st)) > 0)
+            printf("%s %zu\n", rcc_lex_token_name(t), st.len);
+        if (t == RCC_LEX_ERROR)
+            printf("error\n");
+        printf("eof\n");
+    }
+    return 0;
+}
+"#;
+
+    #[test]
+    fn compile_and_run() {
+        let s = parse_spec("test.l", SPEC, re::Config::default())
+            .unwrap()
+            .compile()
+            .unwrap();
+        let code = CCode::new(&s, "rcc_lex").unwrap();
+        let dir = std::env::temp_dir().join(format!("rcclex-c-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("rcc_lex.h"), code.header()).unwrap();
+        std::fs::write(dir.join("rcc_lex.c"), code.source()).unwrap();
+        std::fs::write(dir.join("main.c"), MAIN).unwrap();
+        let cc = std::env::var("CC").unwrap_or(String::from("cc"));
+        let status = Command::new(cc)
+            .current_dir(&dir)
+            .args(["-std=c99", "-Wall", "-Werror", "-pedantic", "-o", "lexer"])
+            .args(["rcc_lex.c", "main.c"])
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let out = Command::new(dir.join("lexer"))
+            .args(INPUTS.iter().map(|s| std::str::from_utf8(s).unwrap()))
+            .output()
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let expected: String = INPUTS.iter().map(|i| interpret(&s, i)).collect();
+        assert_eq!(String::from_utf8(out.stdout).unwrap(), expected);
+    }
+
+    #[test]
+    fn lazy() {
+        let config = re::Config {
+            engine: re::Engine::LazyDFA,
+            ..re::Config::default()
+        };
+        let s = parse_spec("test.l", SPEC, config)
+            .unwrap()
+            .compile()
+            .unwrap();
+        assert_eq!(CCode::new(&s, "rcc_lex").err(), Some(Error::Lazy));
+    }
+
+    #[test]
+    fn kinds() {
+        for (kind, ok) in [
+            ("IDENT", true),
+            ("_x1", true),
+            ("ERROR", false),
+            ("EOF", false),
+            ("1X", false),
+            ("A-B", false),
+            ("", false),
+            ("H", false),
+        ] {
+            let s = LexerSpec::new(re::Config::default())
+                .rule(b"a", String::from(kind))
+                .compile()
+                .unwrap();
+            let res = CCode::new(&s, "rcc_lex").err();
+            assert_eq!(
+                res,
+                (!ok).then(|| Error::Kind(String::from(kind))),
+                "{kind}"
+            );
+        }
+        let s = LexerSpec::new(re::Config::default())
+            .rule(b"a", String::from("A"))
+            .compile()
+            .unwrap();
+        for name in ["rcc-lex", "1lex", ""] {
+            let res = CCode::new(&s, name).err();
+            assert_eq!(res, Some(Error::Name(String::from(name))), "{name}");
+        }
+    }
+}
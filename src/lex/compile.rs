@@ -0,0 +1,176 @@
+pub mod lex {
+    use crate::re;
+
+    /// token definition: bytes matched by `pattern` produce token `kind`
+    pub struct Rule<K> {
+        pub pattern: Vec<u8>,
+        pub kind: K,
+    }
+
+    /// ordered list of rules, earlier rules win on equal match length
+    pub struct LexerSpec<K> {
+        pub rules: Vec<Rule<K>>,
+        pub config: re::Config,
+    }
+
+    /// combined dfa of all rules
+    pub struct Scanner<K> {
+        pub classes: re::ByteClasses,
+        /// nodes[n][class] is the next state or re::DEAD
+        pub nodes: Vec<Vec<usize>>,
+        /// start state for each look behind (see re::LOOKS)
+        pub start: Vec<usize>,
+        /// rule, accepted by each state for each look ahead (first rule, if many)
+        pub accept: Vec<Vec<Option<usize>>>,
+        pub kinds: Vec<K>,
+        /// with re::Engine::LazyDFA, states are built while scanning and the tables above are empty
+        pub lazy: Option<re::LazyDFA>,
+    }
+
+    impl<K> LexerSpec<K> {
+        pub fn new(config: re::Config) -> LexerSpec<K> {
+            LexerSpec {
+                rules: Vec::new(),
+                config,
+            }
+        }
+
+        /// append rule with the lowest priority
+        pub fn rule(mut self, pattern: &[u8], kind: K) -> LexerSpec<K> {
+            self.rules.push(Rule {
+                pattern: Vec::from(pattern),
+                kind,
+            });
+            self
+        }
+
+        /// compile rules into a dfa, or into a lazy dfa with re::Engine::LazyDFA (code can't be generated then)
+        pub fn compile(self) -> Result<Scanner<K>> {
+            let patterns: Vec<&[u8]> = self.rules.iter().map(|r| &r.pattern[..]).collect();
+            let rule_error = |(r, e): (Option<usize>, re::Diagnostic)| match (r, &e.error) {
+                (None, re::Error::Config) => Error::Config,
+                (None, _) => Error::TooLarge,
+                (Some(r), _) => Error::Rule(r, e),
+            };
+            if self.config.engine == re::Engine::LazyDFA {
+                let lazy = re::compile_rules_lazy(&patterns, self.config).map_err(rule_error)?;
+                return Ok(Scanner {
+                    classes: lazy.classes().clone(),
+                    nodes: vec![],
+                    start: vec![],
+                    accept: vec![],
+                    kinds: self.rules.into_iter().map(|r| r.kind).collect(),
+                    lazy: Some(lazy),
+                });
+            }
+            let dfa = re::compile_rules(&patterns, self.config).map_err(rule_error)?;
+            Ok(Scanner {
+                classes: dfa.classes,
+                nodes: dfa.nodes,
+                start: dfa.start,
+                accept: dfa
+                    .accept
+                    .iter()
+                    .map(|a| a.iter().map(|s| s.iter().min().copied()).collect())
+                    .collect(),
+                kinds: self.rules.into_iter().map(|r| r.kind).collect(),
+                lazy: None,
+            })
+        }
+    }
+
+    pub type Result<T> = std::result::Result<T, Error>;
+
+    /// errors, that can happen during lexer compilation, spec parsing or scanning
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Error {
+        Config,
+        TooLarge,
+        /// code can't be generated for a scanner with re::Engine::LazyDFA
+        Lazy,
+        /// token kind, that is not a valid name in generated code
+        Kind(String),
+        /// name of generated C code, that is not an identifier
+        Name(String),
+        Rule(usize, re::Diagnostic),
+        NoMatch(usize),
+        Section,
+        Syntax,
+        Undefined(String),
+    }
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            match self {
+                Self::Config => write!(f, "invalid regex configuration"),
+                Self::TooLarge => write!(f, "rules exceed compilation limits"),
+                Self::Lazy => write!(f, "code can't be generated for a lazy dfa scanner"),
+                Self::Kind(k) => {
+                    write!(f, "token kind '{k}' is not a valid name in generated code")
+                }
+                Self::Name(n) => write!(f, "'{n}' is not a valid C name"),
+                Self::Rule(r, e) => write!(f, "rule #{r}: {e}"),
+                Self::NoMatch(n) => write!(f, "no rule matches input at offset {n}"),
+                Self::Section => write!(f, "missing '%%' after definitions"),
+                Self::Syntax => write!(f, "bad definition or rule syntax"),
+                Self::Undefined(s) => write!(f, "undefined name '{s}'"),
+            }
+        }
+    }
+
+    /// ascii identifier, as accepted by both C and Rust
+    fn is_ident(s: &str) -> bool {
+        let first = s
+            .bytes()
+            .next()
+            .is_some_and(|c| c == b'_' || c.is_ascii_alphabetic());
+        first && s.bytes().all(|c| c == b'_' || c.is_ascii_alphanumeric())
+    }
+
+    include!("scanner.rs");
+    include!("spec.rs");
+    include!("rust.rs");
+    include!("c.rs");
+
+    /// lexer spec and inputs, shared by tests of code generators
+    #[cfg(test)]
+    mod test_codegen {
+        use super::*;
+
+        pub const SPEC: &[u8] = b"D [0-9]
+%%
+if|else|while   KEYWORD
+[a-zA-Z_]\\w*   IDENT
+{D}+            NUMBER
+0[xX][0-9a-f]+  HEX
+\"([^\"\\\\]|\\\\.)*\"  STRING
+[-+*/=<>!]=?    OP
+[ \\t\\n]+        SPACE
+^#[a-z]+        DIRECTIVE
+";
+
+        pub const INPUTS: &[&[u8]] = &[
+            b"if x1 >= 10 else y",
+            b"while(1)",
+            b"a = \"q\\\"w\" + 0x1f;",
+            b"",
+            b"ifelse 0xZ",
+            b"#include x",
+            b"x #if",
+        ];
+
+        pub fn interpret(s: &Scanner<String>, input: &[u8]) -> String {
+            let mut res = String::new();
+            for t in s.tokens(input) {
+                match t {
+                    Ok(t) => res += &format!("{} {}\n", t.kind, t.text.len()),
+                    Err(_) => {
+                        res += "error\n";
+                        break;
+                    }
+                }
+            }
+            res + "eof\n"
+        }
+    }
+}
@@ -0,0 +1,216 @@
+/*
+ * Rust code generation
+ *
+ * - token kinds become `TokenKind` enum, kinds must be valid identifiers
+ * - dfa becomes byte class table and dense transition table over classes
+ * - `next_token` implements the same longest match as `Scanner::longest_at`
+ */
+
+const NEXT_TOKEN: &str = r#"fn look(c: Option<&u8>) -> usize {
+    c.map_or(0, |c| LOOK[CLASS[*c as usize] as usize] as usize)
+}
+
+/// longest non-empty match at `s[pos..]`: (token kind, length)
+/// anchors see bytes before `pos`
+pub fn next_token(s: &[u8], pos: usize) -> Option<(TokenKind, usize)> {
+    let mut state = START[look(s[..pos].last())] as usize;
+    let mut res = None;
+    for (i, c) in s.iter().enumerate().skip(pos) {
+        match NEXT[state][CLASS[*c as usize] as usize] {
+            DEAD => break,
+            next => state = next as usize,
+        }
+        if let Some(k) = ACCEPT[state][look(s.get(i + 1))] {
+            res = Some((k, i + 1 - pos));
+        }
+    }
+    res
+}
+"#;
+
+/// strict and reserved keywords of rust 2021, that can't name an enum variant
+const RUST_KEYWORDS: &[&str] = &[
+    "_", "abstract", "as", "async", "await", "become", "box", "break", "const", "continue",
+    "crate", "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// generated rust module for a compiled lexer
+pub struct RustCode<'a, K>(&'a Scanner<K>);
+
+impl<'a, K: std::fmt::Display> RustCode<'a, K> {
+    /// fails for a scanner without tables (see re::Engine::LazyDFA),
+    /// and for token kinds, that are not identifiers or are rust keywords
+    pub fn new(scanner: &'a Scanner<K>) -> Result<RustCode<'a, K>> {
+        if scanner.lazy.is_some() {
+            return Err(Error::Lazy);
+        }
+        match scanner
+            .kind_names()
+            .into_iter()
+            .find(|k| !is_ident(k) || RUST_KEYWORDS.contains(&k.as_str()))
+        {
+            Some(k) => Err(Error::Kind(k)),
+            None => Ok(RustCode(scanner)),
+        }
+    }
+}
+
+impl<K: std::fmt::Display> RustCode<'_, K> {
+    fn table(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (n, classes) = (self.0.nodes.len(), self.0.classes.count);
+        writeln!(f, "static CLASS: [u8; 256] = [")?;
+        for row in self.0.classes.map.chunks(16) {
+            write!(f, "   ")?;
+            for c in row {
+                write!(f, " {c},")?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "];\n")?;
+        let look: Vec<String> = self.0.classes.look.iter().map(|l| l.to_string()).collect();
+        writeln!(f, "static LOOK: [u8; {classes}] = [{}];\n", look.join(", "))?;
+        let start: Vec<String> = self.0.start.iter().map(|s| s.to_string()).collect();
+        writeln!(
+            f,
+            "static START: [u32; {}] = [{}];\n",
+            re::LOOKS,
+            start.join(", ")
+        )?;
+        writeln!(f, "static NEXT: [[u32; {classes}]; {n}] = [")?;
+        for node in self.0.nodes.iter() {
+            write!(f, "    [")?;
+            for s in node {
+                match *s {
+                    re::DEAD => write!(f, "DEAD, ")?,
+                    s => write!(f, "{s}, ")?,
+                }
+            }
+            writeln!(f, "],")?;
+        }
+        writeln!(f, "];\n")?;
+        writeln!(
+            f,
+            "static ACCEPT: [[Option<TokenKind>; {}]; {n}] = [",
+            re::LOOKS
+        )?;
+        for a in self.0.accept.iter() {
+            write!(f, "    [")?;
+            for r in a {
+                match r {
+                    Some(r) => write!(f, "Some(TokenKind::{}), ", self.0.kinds[*r])?,
+                    None => write!(f, "None, ")?,
+                }
+            }
+            writeln!(f, "],")?;
+        }
+        writeln!(f, "];\n")
+    }
+}
+
+impl<K: std::fmt::Display> std::fmt::Display for RustCode<'_, K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "// generated by rcclex, do not edit\n")?;
+        writeln!(f, "#[allow(non_camel_case_types, dead_code)]")?;
+        writeln!(f, "#[derive(Debug, Clone, Copy, PartialEq, Eq)]")?;
+        writeln!(f, "pub enum TokenKind {{")?;
+        for k in self.0.kind_names() {
+            writeln!(f, "    {k},")?;
+        }
+        writeln!(f, "}}\n")?;
+        writeln!(f, "const DEAD: u32 = u32::MAX;\n")?;
+        self.table(f)?;
+        f.write_str(NEXT_TOKEN)
+    }
+}
+
+#[cfg(test)]
+mod test_rust {
+    use super::test_codegen::{interpret, INPUTS, SPEC};
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn compile_and_run() {
+        let s = parse_spec("test.l", SPEC, re::Config::default())
+            .unwrap()
+            .compile()
+            .unwrap();
+        let mut code = RustCode::new(&s).unwrap().to_string();
+        code += r#"
+fn main() {
+    for arg in std::env::args().skip(1) {
+        let (s, mut pos) = (arg.as_bytes(), 0);
+        while let Some((k, n)) = next_token(s, pos) {
+            println!("{:?} {}", k, n);
+            pos += n;
+        }
+        if pos != s.len() {
+            println!("error");
+        }
+        println!("eof");
+    }
+}
+"#;
+        let dir = std::env::temp_dir().join(format!("rcclex-rust-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("lexer.rs"), code).unwrap();
+        let rustc = std::env::var("RUSTC").unwrap_or(String::from("rustc"));
+        let status = Command::new(rustc)
+            .current_dir(&dir)
+            .args(["--edition=2021", "-Dwarnings", "-o", "lexer", "lexer.rs"])
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let out = Command::new(dir.join("lexer"))
+            .args(INPUTS.iter().map(|s| std::str::from_utf8(s).unwrap()))
+            .output()
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let expected: String = INPUTS.iter().map(|i| interpret(&s, i)).collect();
+        assert_eq!(String::from_utf8(out.stdout).unwrap(), expected);
+    }
+
+    #[test]
+    fn lazy() {
+        let config = re::Config {
+            engine: re::Engine::LazyDFA,
+            ..re::Config::default()
+        };
+        let s = parse_spec("test.l", SPEC, config)
+            .unwrap()
+            .compile()
+            .unwrap();
+        assert_eq!(RustCode::new(&s).err(), Some(Error::Lazy));
+    }
+
+    #[test]
+    fn kinds() {
+        for (kind, ok) in [
+            ("IDENT", true),
+            ("_x1", true),
+            ("r#fn", false),
+            ("A-B", false),
+            ("0", false),
+            ("", false),
+            ("_", false),
+            ("fn", false),
+            ("Self", false),
+            ("type", false),
+        ] {
+            let s = LexerSpec::new(re::Config::default())
+                .rule(b"a", String::from(kind))
+                .compile()
+                .unwrap();
+            let res = RustCode::new(&s).err();
+            assert_eq!(
+                res,
+                (!ok).then(|| Error::Kind(String::from(kind))),
+                "{kind}"
+            );
+        }
+    }
+}
@@ -0,0 +1,247 @@
+/*
+ * Lexer runtime
+ *
+ * - longest match wins, on equal length the first rule wins
+ * - empty matches are not tokens: scanning fails, if nothing but empty string matches
+ * - anchors see the whole input: a token starts at a line only after a newline
+ */
+
+#[derive(Debug, PartialEq)]
+pub struct Token<'a, K> {
+    pub kind: K,
+    pub text: &'a [u8],
+    pub offset: usize,
+}
+
+pub struct Tokens<'a, K> {
+    scanner: &'a Scanner<K>,
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl<K: std::fmt::Display> Scanner<K> {
+    /// unique token kinds, in order of the first rule
+    pub fn kind_names(&self) -> Vec<String> {
+        let mut kinds: Vec<String> = Vec::new();
+        for k in self.kinds.iter().map(|k| k.to_string()) {
+            if !kinds.contains(&k) {
+                kinds.push(k);
+            }
+        }
+        kinds
+    }
+}
+
+impl<K: Clone> Scanner<K> {
+    /// longest match at the beginning of `s`: (length, rule)
+    pub fn longest(&self, s: &[u8]) -> Option<(usize, usize)> {
+        self.longest_at(s, 0)
+    }
+
+    /// longest match at s[pos..]: (length, rule)
+    pub fn longest_at(&self, s: &[u8], pos: usize) -> Option<(usize, usize)> {
+        if let Some(lazy) = &self.lazy {
+            return lazy.longest_at(s, pos).map(|(end, r)| (end - pos, r));
+        }
+        let accept = |n: usize, i: usize| self.accept[n][self.classes.look(s.get(i).copied())];
+        let mut state = self.start[self.classes.look(s[..pos].last().copied())];
+        let mut res = accept(state, pos).map(|r| (0, r));
+        for (i, c) in s.iter().enumerate().skip(pos) {
+            match re::next_state(&self.classes, &self.nodes, state, *c) {
+                Some(n) => state = n,
+                None => break,
+            }
+            if let Some(r) = accept(state, i + 1) {
+                res = Some((i + 1 - pos, r));
+            }
+        }
+        res
+    }
+
+    /// split `s` into tokens
+    pub fn tokens<'a>(&'a self, s: &'a [u8]) -> Tokens<'a, K> {
+        Tokens {
+            scanner: self,
+            s,
+            pos: 0,
+        }
+    }
+}
+
+impl<'a, K: Clone> Iterator for Tokens<'a, K> {
+    type Item = Result<Token<'a, K>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos == self.s.len() {
+            return None;
+        }
+        let offset = self.pos;
+        match self.scanner.longest_at(self.s, offset) {
+            Some((len, r)) if len != 0 => {
+                self.pos += len;
+                Some(Ok(Token {
+                    kind: self.scanner.kinds[r].clone(),
+                    text: &self.s[offset..self.pos],
+                    offset,
+                }))
+            }
+            _ => {
+                self.pos = self.s.len();
+                Some(Err(Error::NoMatch(offset)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_scanner {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Kind {
+        If,
+        Ident,
+        Number,
+        Op,
+        Space,
+    }
+
+    fn scanner() -> Scanner<Kind> {
+        LexerSpec::new(re::Config::default())
+            .rule(b"if", Kind::If)
+            .rule(b"[a-z_]\\w*", Kind::Ident)
+            .rule(b"\\d+", Kind::Number)
+            .rule(b"[=<>]|==|<=|>=", Kind::Op)
+            .rule(b"\\s+", Kind::Space)
+            .compile()
+            .unwrap()
+    }
+
+    fn kinds<'a>(s: &'a Scanner<Kind>, input: &'a [u8]) -> Result<Vec<(Kind, &'a [u8])>> {
+        s.tokens(input)
+            .map(|t| t.map(|t| (t.kind, t.text)))
+            .collect()
+    }
+
+    #[test]
+    fn longest_match() {
+        let s = scanner();
+        assert_eq!(s.longest(b"if x"), Some((2, 0)));
+        assert_eq!(s.longest(b"iffy"), Some((4, 1)));
+        assert_eq!(s.longest(b"<=1"), Some((2, 3)));
+        assert_eq!(s.longest(b"+"), None);
+    }
+
+    #[test]
+    fn lazy() {
+        let config = re::Config {
+            engine: re::Engine::LazyDFA,
+            lazy_cache: 2048,
+            ..re::Config::default()
+        };
+        let lazy = LexerSpec::new(config)
+            .rule(b"if", Kind::If)
+            .rule(b"[a-z_]\\w*", Kind::Ident)
+            .rule(b"\\d+", Kind::Number)
+            .rule(b"[=<>]|==|<=|>=", Kind::Op)
+            .rule(b"\\s+", Kind::Space)
+            .compile()
+            .unwrap();
+        assert!(lazy.lazy.is_some() && lazy.nodes.is_empty());
+        let input = b"if iffy <= 12 x_1 >= if2 + x";
+        assert_eq!(kinds(&lazy, input), kinds(&scanner(), input));
+        assert_eq!(lazy.longest_at(b"x <=", 2), Some((2, 3)));
+    }
+
+    #[test]
+    fn anchors() {
+        let config = re::Config {
+            multiline: true,
+            ..re::Config::default()
+        };
+        let s = LexerSpec::new(config)
+            .rule(b"^#[^\\n]*", Kind::Op)
+            .rule(b"[a-z#]+", Kind::Ident)
+            .rule(b"\\s+", Kind::Space)
+            .compile()
+            .unwrap();
+        assert_eq!(s.longest_at(b"a #b", 2), Some((2, 1)));
+        assert_eq!(
+            kinds(&s, b"#a b#c\n#d"),
+            Ok(vec![
+                (Kind::Op, &b"#a b#c"[..]),
+                (Kind::Space, &b"\n"[..]),
+                (Kind::Op, &b"#d"[..]),
+            ])
+        );
+        assert_eq!(
+            kinds(&s, b"a #b"),
+            Ok(vec![
+                (Kind::Ident, &b"a"[..]),
+                (Kind::Space, &b" "[..]),
+                (Kind::Ident, &b"#b"[..]),
+            ])
+        );
+    }
+
+    #[test]
+    fn tokens() {
+        let s = scanner();
+        assert_eq!(
+            kinds(&s, b"if iff>=10 x_1"),
+            Ok(vec![
+                (Kind::If, &b"if"[..]),
+                (Kind::Space, &b" "[..]),
+                (Kind::Ident, &b"iff"[..]),
+                (Kind::Op, &b">="[..]),
+                (Kind::Number, &b"10"[..]),
+                (Kind::Space, &b" "[..]),
+                (Kind::Ident, &b"x_1"[..]),
+            ])
+        );
+        let t: Vec<_> = s.tokens(b"a b").collect();
+        assert_eq!(
+            t[2],
+            Ok(Token {
+                kind: Kind::Ident,
+                text: b"b",
+                offset: 2
+            })
+        );
+        assert_eq!(kinds(&s, b""), Ok(vec![]));
+        assert_eq!(kinds(&s, b"a + b"), Err(Error::NoMatch(2)));
+    }
+
+    #[test]
+    fn errors() {
+        let spec = LexerSpec::new(re::Config::default())
+            .rule(b"a", 0)
+            .rule(b"(b", 1);
+        assert_eq!(
+            spec.compile().err(),
+            Some(Error::Rule(1, re::Error::Balance.at(2..2)))
+        );
+        let spec = LexerSpec::new(re::Config::default())
+            .rule(b"a", 0)
+            .rule(b"b{100}{100}", 1);
+        assert_eq!(
+            spec.compile().err(),
+            Some(Error::Rule(1, re::Error::TooLarge.at(6..11)))
+        );
+        let config = re::Config {
+            max_nfa_nodes: 100,
+            ..re::Config::default()
+        };
+        let spec = LexerSpec::new(config).rule(b"a{30}", 0).rule(b"b{30}", 1);
+        assert_eq!(spec.compile().err(), Some(Error::TooLarge));
+
+        let s = LexerSpec::new(re::Config::default())
+            .rule(b"a*", 0)
+            .compile()
+            .unwrap();
+        assert_eq!(
+            s.tokens(b"aab").collect::<Vec<_>>()[1],
+            Err(Error::NoMatch(2))
+        );
+    }
+}
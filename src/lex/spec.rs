@@ -0,0 +1,341 @@
+/*
+ * Lexer specification file
+ *
+ * definitions
+ * %%
+ * rules
+ * %%
+ * user code
+ *
+ * - definition line: `NAME pattern`, rule line: `pattern TOKEN`
+ * - `{NAME}` within a pattern, but not within `[...]`, is replaced with `(pattern)` of an earlier definition
+ * - trailing whitespace of a pattern is dropped, unless it is an escaped space or tab
+ * - empty lines and lines, starting with '#', are skipped in definitions and rules
+ * - user code section is optional and kept as is
+ */
+
+/// position within a spec file, lines start from 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pos {
+    pub file: String,
+    pub line: usize,
+}
+
+/// error with the position in the spec file, where it is found
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub error: Error,
+    /// none for errors of limits and config, that do not depend on a rule
+    pub pos: Option<Pos>,
+}
+
+impl From<Error> for Diagnostic {
+    fn from(error: Error) -> Diagnostic {
+        Diagnostic { error, pos: None }
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.pos {
+            Some(p) => write!(f, "{}:{}: {}", p.file, p.line, self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl Error {
+    /// error at a position of the spec file
+    pub fn at(self, pos: Pos) -> Diagnostic {
+        Diagnostic {
+            error: self,
+            pos: Some(pos),
+        }
+    }
+}
+
+pub struct SpecFile {
+    pub definitions: Vec<(String, Vec<u8>)>,
+    pub spec: LexerSpec<String>,
+    /// position of each rule
+    pub pos: Vec<Pos>,
+    pub code: Vec<u8>,
+}
+
+/// parse spec file `s`, `file` is only used for error positions
+pub fn parse_spec(
+    file: &str,
+    s: &[u8],
+    config: re::Config,
+) -> std::result::Result<SpecFile, Diagnostic> {
+    let mut res = SpecFile {
+        definitions: Vec::new(),
+        spec: LexerSpec::new(config),
+        pos: Vec::new(),
+        code: Vec::new(),
+    };
+    let mut section = 0;
+    for (i, line) in s.split_inclusive(|c| *c == b'\n').enumerate() {
+        let pos = Pos {
+            file: String::from(file),
+            line: i + 1,
+        };
+        if section == 2 {
+            res.code.extend(line);
+            continue;
+        }
+        let line = trim_unescaped(line.trim_ascii_start());
+        if line == b"%%" {
+            section += 1;
+            continue;
+        }
+        if line.is_empty() || line[0] == b'#' {
+            continue;
+        }
+        let at = |e: Error| e.at(pos.clone());
+        let (left, right) = split_line(line, section == 0).ok_or(at(Error::Syntax))?;
+        if section == 0 {
+            let pattern = res.expand(right).map_err(at)?;
+            res.definitions
+                .push((String::from_utf8_lossy(&left).into(), pattern));
+        } else {
+            let pattern = res.expand(&left).map_err(at)?;
+            res.spec = res
+                .spec
+                .rule(&pattern, String::from_utf8_lossy(right).into());
+            res.pos.push(pos);
+        }
+    }
+    if section == 0 {
+        return Err(Error::Section.at(Pos {
+            file: String::from(file),
+            line: s.split_inclusive(|c| *c == b'\n').count().max(1),
+        }));
+    }
+    Ok(res)
+}
+
+/// split line into name and pattern (definition) or pattern and name (rule)
+/// name is a non-empty identifier
+fn split_line(line: &[u8], definition: bool) -> Option<(Vec<u8>, &[u8])> {
+    let at = match definition {
+        true => line.iter().position(|c| c.is_ascii_whitespace())?,
+        false => line.iter().rposition(|c| c.is_ascii_whitespace())? + 1,
+    };
+    let (left, right) = line.split_at(at);
+    let (left, right) = (
+        trim_unescaped(left.trim_ascii_start()),
+        trim_unescaped(right.trim_ascii_start()),
+    );
+    let name = if definition { left } else { right };
+    if !is_name(name) || left.is_empty() || right.is_empty() {
+        return None;
+    }
+    Some((Vec::from(left), right))
+}
+
+/// strip trailing whitespace, but a space or a tab, escaped with `\`
+fn trim_unescaped(s: &[u8]) -> &[u8] {
+    let end = s.trim_ascii_end().len();
+    let slashes = s[..end].iter().rev().take_while(|c| **c == b'\\').count();
+    match s.get(end) {
+        Some(b' ' | b'\t') if slashes % 2 == 1 => &s[..end + 1],
+        _ => &s[..end],
+    }
+}
+
+fn is_name(s: &[u8]) -> bool {
+    match s.first() {
+        Some(c) if c.is_ascii_alphabetic() || *c == b'_' => {
+            s.iter().all(|c| c.is_ascii_alphanumeric() || *c == b'_')
+        }
+        _ => false,
+    }
+}
+
+impl SpecFile {
+    /// replace `{NAME}` references with definitions, outside of bracket expressions
+    fn expand(&self, s: &[u8]) -> Result<Vec<u8>> {
+        let mut res = Vec::new();
+        let mut i = 0;
+        // start of the bracket expression, that `i` is in: `]` right after it is a literal
+        let mut bracket: Option<usize> = None;
+        while i < s.len() {
+            match s[i] {
+                b'\\' => {
+                    res.extend(&s[i..(i + 2).min(s.len())]);
+                    i += 2;
+                    continue;
+                }
+                b'[' if bracket.is_none() => {
+                    let inv = s.get(i + 1) == Some(&b'^');
+                    let start = i + 1 + inv as usize;
+                    res.extend(&s[i..start]);
+                    bracket = Some(start);
+                    i = start;
+                    continue;
+                }
+                b']' if bracket.is_some_and(|start| i > start) => {
+                    bracket = None;
+                    res.push(b']');
+                }
+                b'{' if bracket.is_none()
+                    && s.get(i + 1)
+                        .is_some_and(|c| c.is_ascii_alphabetic() || *c == b'_') =>
+                {
+                    let len = s[i..]
+                        .iter()
+                        .position(|c| *c == b'}')
+                        .ok_or(Error::Syntax)?;
+                    let name = &s[i + 1..i + len];
+                    let (_, def) = self
+                        .definitions
+                        .iter()
+                        .find(|(n, _)| n.as_bytes() == name)
+                        .ok_or(Error::Undefined(String::from_utf8_lossy(name).into()))?;
+                    res.push(b'(');
+                    res.extend(def);
+                    res.push(b')');
+                    i += len + 1;
+                    continue;
+                }
+                c => res.push(c),
+            }
+            i += 1;
+        }
+        Ok(res)
+    }
+
+    /// compile rules, errors are reported with rule positions
+    pub fn compile(self) -> std::result::Result<Scanner<String>, Diagnostic> {
+        let pos = self.pos;
+        self.spec.compile().map_err(|e| match e {
+            Error::Rule(r, _) => e.at(pos[r].clone()),
+            e => e.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_spec {
+    use super::*;
+
+    const SPEC: &[u8] = b"# numbers
+DIGIT   [0-9]
+NUMBER  {DIGIT}+(\\.{DIGIT}+)?
+%%
+if          IF
+[a-z]\\w*    IDENT
+
+{NUMBER}    NUMBER
+\\{{DIGIT}{2}\\}  CODE
+[ ]+        SPACE
+%%
+fn main() {}
+";
+
+    fn parse(s: &[u8]) -> std::result::Result<SpecFile, Diagnostic> {
+        parse_spec("test.l", s, re::Config::default())
+    }
+
+    fn err(line: usize, e: Error) -> Diagnostic {
+        e.at(Pos {
+            file: String::from("test.l"),
+            line,
+        })
+    }
+
+    #[test]
+    fn parse_file() {
+        let spec = parse(SPEC).unwrap();
+        assert_eq!(
+            spec.definitions,
+            vec![
+                (String::from("DIGIT"), Vec::from(b"[0-9]")),
+                (String::from("NUMBER"), Vec::from(b"([0-9])+(\\.([0-9])+)?")),
+            ]
+        );
+        let rules: Vec<(&[u8], &str)> = spec
+            .spec
+            .rules
+            .iter()
+            .map(|r| (&r.pattern[..], &r.kind[..]))
+            .collect();
+        assert_eq!(
+            rules,
+            vec![
+                (&b"if"[..], "IF"),
+                (b"[a-z]\\w*", "IDENT"),
+                (b"(([0-9])+(\\.([0-9])+)?)", "NUMBER"),
+                (b"\\{([0-9]){2}\\}", "CODE"),
+                (b"[ ]+", "SPACE"),
+            ]
+        );
+        assert_eq!(
+            spec.pos.iter().map(|p| p.line).collect::<Vec<_>>(),
+            vec![5, 6, 8, 9, 10]
+        );
+        assert_eq!(spec.code, b"fn main() {}\n");
+
+        let s = spec.compile().unwrap();
+        let t: Vec<String> = s
+            .tokens(b"if x1 {12} 3.14")
+            .map(|t| t.unwrap().kind)
+            .collect();
+        assert_eq!(
+            t,
+            vec!["IF", "SPACE", "IDENT", "SPACE", "CODE", "SPACE", "NUMBER"]
+        );
+    }
+
+    #[test]
+    fn brackets() {
+        let rules = |s: &[u8]| -> Vec<Vec<u8>> {
+            let spec = parse(s).unwrap().spec;
+            spec.rules.into_iter().map(|r| r.pattern).collect()
+        };
+        assert_eq!(
+            rules(b"x a\n%%\n[{] A\n[{x}] B\n[]{x}]{x} C\n[^]{x}] D\n[{a-z]+ E\n"),
+            vec![
+                Vec::from(b"[{]"),
+                Vec::from(b"[{x}]"),
+                Vec::from(b"[]{x}](a)"),
+                Vec::from(b"[^]{x}]"),
+                Vec::from(b"[{a-z]+"),
+            ]
+        );
+        assert!(parse(b"%%\n[{a-z]+ A\n").unwrap().compile().is_ok());
+    }
+
+    #[test]
+    fn escaped_space() {
+        let spec = parse(b"S a\\ \n%%\n{S}b\\  A\na\\\\  B\n").unwrap();
+        assert_eq!(spec.definitions[0].1, b"a\\ ");
+        let rules: Vec<&[u8]> = spec.spec.rules.iter().map(|r| &r.pattern[..]).collect();
+        assert_eq!(rules, vec![&b"(a\\ )b\\ "[..], b"a\\\\"]);
+        let s = spec.compile().unwrap();
+        assert_eq!(s.longest(b"a b x"), Some((4, 0)));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse(b"A a\n\nB b\n").err(), Some(err(3, Error::Section)));
+        assert_eq!(parse(b"A\n%%\n").err(), Some(err(1, Error::Syntax)));
+        assert_eq!(parse(b"1A a\n%%\n").err(), Some(err(1, Error::Syntax)));
+        assert_eq!(parse(b"%%\na\n").err(), Some(err(2, Error::Syntax)));
+        assert_eq!(parse(b"%%\na 1\n").err(), Some(err(2, Error::Syntax)));
+        assert_eq!(
+            parse(b"A a\n%%\n{B} B\n").err(),
+            Some(err(3, Error::Undefined(String::from("B"))))
+        );
+        assert_eq!(parse(b"%%\n{A B\n").err(), Some(err(2, Error::Syntax)));
+        assert_eq!(
+            parse(b"%%\na A\n\n(b B\n").unwrap().compile().err(),
+            Some(err(4, Error::Rule(1, re::Error::Balance.at(2..2))))
+        );
+        assert_eq!(
+            format!("{}", parse(b"%%\n(b B\n").unwrap().compile().err().unwrap()),
+            "test.l:2: rule #0: bad () balance at offset 2"
+        );
+    }
+}
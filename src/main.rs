@@ -1,4 +1,12 @@
+// the code base keeps explicit returns, single-arm matches and the tests include before main
+#![allow(
+    clippy::needless_return,
+    clippy::single_match,
+    clippy::items_after_test_module
+)]
+
 include!("re/compile.rs");
+include!("lex/compile.rs");
 include!("graphviz.rs");
 include!("tests.rs");
 
@@ -0,0 +1,127 @@
+/*
+ * Zero-width assertions
+ *
+ * - an assertion is checked on looks around the position: of the previous and of the next byte
+ * - look is the edge of the input, a newline, a word char (as defined by \w) or any other byte
+ * - dfa states remember look behind, accepted rules depend on look ahead
+ */
+
+pub const LOOK_EDGE: usize = 0;
+pub const LOOK_NEWLINE: usize = 1;
+pub const LOOK_OTHER: usize = 2;
+pub const LOOK_WORD: usize = 3;
+/// number of looks
+pub const LOOKS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Assert {
+    TextStart,       // ^
+    TextEnd,         // $
+    LineStart,       // ^ in multiline mode
+    LineEnd,         // $ in multiline mode
+    WordBoundary,    // \b
+    NotWordBoundary, // \B
+}
+
+impl Assert {
+    /// check assertion between looks `prev` and `next`
+    pub fn check(&self, prev: usize, next: usize) -> bool {
+        match self {
+            Self::TextStart => prev == LOOK_EDGE,
+            Self::TextEnd => next == LOOK_EDGE,
+            Self::LineStart => prev == LOOK_EDGE || prev == LOOK_NEWLINE,
+            Self::LineEnd => next == LOOK_EDGE || next == LOOK_NEWLINE,
+            Self::WordBoundary => (prev == LOOK_WORD) != (next == LOOK_WORD),
+            Self::NotWordBoundary => (prev == LOOK_WORD) == (next == LOOK_WORD),
+        }
+    }
+
+    /// assertion depends on word chars
+    pub fn word(&self) -> bool {
+        matches!(self, Self::WordBoundary | Self::NotWordBoundary)
+    }
+
+    /// same assertion for reversed input
+    pub fn reverse(&self) -> Assert {
+        match self {
+            Self::TextStart => Self::TextEnd,
+            Self::TextEnd => Self::TextStart,
+            Self::LineStart => Self::LineEnd,
+            Self::LineEnd => Self::LineStart,
+            x => *x,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_assert {
+    use super::*;
+
+    #[test]
+    fn check() {
+        assert!(Assert::TextStart.check(LOOK_EDGE, LOOK_OTHER));
+        assert!(!Assert::TextStart.check(LOOK_NEWLINE, LOOK_OTHER));
+        assert!(Assert::LineStart.check(LOOK_NEWLINE, LOOK_OTHER));
+        assert!(!Assert::LineEnd.check(LOOK_NEWLINE, LOOK_OTHER));
+        assert!(Assert::LineEnd.check(LOOK_OTHER, LOOK_NEWLINE));
+        assert!(Assert::TextEnd.check(LOOK_OTHER, LOOK_EDGE));
+        assert_eq!(Assert::LineEnd.reverse(), Assert::LineStart);
+        assert!(Assert::WordBoundary.check(LOOK_EDGE, LOOK_WORD));
+        assert!(Assert::WordBoundary.check(LOOK_WORD, LOOK_NEWLINE));
+        assert!(!Assert::WordBoundary.check(LOOK_WORD, LOOK_WORD));
+        assert!(Assert::NotWordBoundary.check(LOOK_OTHER, LOOK_EDGE));
+        assert!(!Assert::NotWordBoundary.check(LOOK_OTHER, LOOK_WORD));
+        assert_eq!(Assert::WordBoundary.reverse(), Assert::WordBoundary);
+    }
+
+    #[test]
+    fn anchors() {
+        let r = compile(b"^a+$|b$", Config::default()).unwrap();
+        assert!(r.is_match(b"aa"));
+        assert!(r.is_match(b"b"));
+        assert_eq!(r.match_len(b"aab"), None);
+        assert_eq!(r.match_at(b"xab", 2), Some(3));
+        assert_eq!(r.match_at(b"xa", 1), None);
+        assert_eq!(r.match_at(b"b\n", 0), None);
+
+        let config = Config {
+            multiline: true,
+            ..Config::default()
+        };
+        let r = compile(b"^a+$", config).unwrap();
+        assert_eq!(r.match_at(b"x\naa\n", 2), Some(4));
+        assert_eq!(r.match_at(b"xaa", 1), None);
+        assert_eq!(r.match_at(b"aab", 0), None);
+
+        let r = compile(b"a^*b$*", Config::default()).unwrap();
+        assert!(r.is_match(b"ab"));
+    }
+
+    #[test]
+    fn word_boundary() {
+        let r = compile(b"\\ba+\\b", Config::default()).unwrap();
+        assert!(r.is_match(b"aa"));
+        assert_eq!(r.match_len(b"aab"), None);
+        assert_eq!(r.match_len(b"aa b"), Some(2));
+        assert_eq!(r.match_at(b"xa", 1), None);
+        assert_eq!(r.match_at(b"-a-", 1), Some(2));
+
+        let r = compile(b"a\\B.", Config::default()).unwrap();
+        assert!(r.is_match(b"ab"));
+        assert!(!r.is_match(b"a-"));
+
+        let mut config = Config::default();
+        config.esc_charset.insert(b'w', charset!(b'-'));
+        let r = compile(b"\\b-", config).unwrap();
+        assert!(r.is_match(b"-"));
+        assert_eq!(r.match_at(b"--", 1), None);
+        assert_eq!(r.match_at(b"a-", 1), Some(2));
+
+        let mut config = Config::default();
+        config.esc_charset.insert(b'b', charset!(b'x'));
+        assert_eq!(
+            compile(b"a", config).err().map(|e| e.error),
+            Some(Error::Config)
+        );
+    }
+}
@@ -0,0 +1,356 @@
+/*
+ * Regex AST
+ *
+ * - tokens of the lexer are parsed into a tree of chars, assertions, concatenations, alternations,
+ *   repeats and groups, nfa is built from the tree
+ * - a concatenation or an alternation of a single item is the item itself, parentheses make no nodes
+ * - groups are numbered in order of their start: \A starts a group, \Z closes it around the last item
+ * - the parser counts nfa nodes of the tree, so that huge repeats fail before they are expanded
+ * - Display prints the tree back in canonical syntax for Config::default(): unnamed groups are \A(...)\Z,
+ *   nested concatenations and alternations are parenthesized, so that the printed pattern parses
+ *   into the same tree (sets of non-ascii chars need Config::utf8, groups are renumbered in order of their start)
+ */
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ast {
+    /// any byte of the charset
+    Char(Charset),
+    /// any char of the set, utf-8 encoded (utf-8 mode)
+    Unicode(UnicodeSet),
+    Assert(Assert),
+    Concat(Vec<Ast>),
+    Alt(Vec<Ast>),
+    /// item, min and max number of iterations (None is unbounded)
+    Repeat(Box<Ast>, u32, Option<u32>),
+    /// group index, its name and the grouped item
+    Group(usize, Option<String>, Box<Ast>),
+}
+
+/// parser state, shared by all rules of a lexer
+#[derive(Default)]
+struct AstParser {
+    /// number of nfa nodes of parsed trees
+    nodes: usize,
+    groups: usize,
+    names: HashMap<String, usize>,
+}
+
+/// parse the pattern, errors carry the span of the token, where they are found
+pub fn build_ast(mut lex: Lexer) -> std::result::Result<Ast, Diagnostic> {
+    let mut parser = AstParser::default();
+    parser.parse(&mut lex, 0).map_err(|e| e.at(lex.span()))
+}
+
+impl AstParser {
+    fn parse(&mut self, lex: &mut Lexer, scope: usize) -> Result<Ast> {
+        let mut groups: Vec<usize> = Vec::new();
+        let mut alts: Vec<Ast> = Vec::new();
+        let mut seq: Vec<Ast> = Vec::new();
+        let max_nodes = lex.config.max_nfa_nodes;
+        loop {
+            if self.nodes > max_nodes {
+                return Err(Error::TooLarge);
+            }
+            let item = match lex.token()? {
+                Token::Close(eof) => {
+                    if (scope == 0) != eof {
+                        return Err(Error::Balance);
+                    }
+                    if seq.is_empty() {
+                        return Err(Error::Union);
+                    }
+                    alts.push(Ast::concat(seq));
+                    self.nodes += 2 * (alts.len() - 1);
+                    return Ok(Ast::alt(alts));
+                }
+                Token::Open => self.parse(lex, scope + 1)?,
+                Token::Repeat((min, max)) => {
+                    let t = seq.pop().ok_or(Error::Postfix)?;
+                    let size = t.size();
+                    let copies = max.unwrap_or(min.saturating_add(1)) as usize - 1;
+                    let loop_nodes = if max.is_none() { 2 } else { 0 };
+                    self.nodes = self
+                        .nodes
+                        .saturating_add(size.saturating_mul(copies))
+                        .saturating_add(loop_nodes);
+                    Ast::Repeat(Box::new(t), min, max)
+                }
+                Token::Char(charset) => Ast::Char(charset),
+                Token::Assert(x) => Ast::Assert(x),
+                Token::Unicode(set) => Ast::Unicode(set),
+                Token::Union => {
+                    if seq.is_empty() {
+                        return Err(Error::Union);
+                    }
+                    alts.push(Ast::concat(std::mem::take(&mut seq)));
+                    continue;
+                }
+                Token::StartGroup => {
+                    groups.push(self.groups);
+                    self.groups += 1;
+                    continue;
+                }
+                Token::NamedGroup(name) => {
+                    if self.names.insert(name, self.groups).is_some() {
+                        return Err(Error::Name);
+                    }
+                    groups.push(self.groups);
+                    self.groups += 1;
+                    continue;
+                }
+                Token::EndGroup => {
+                    let t = seq.pop().ok_or(Error::Postfix)?;
+                    let g = groups.pop().ok_or(Error::Group)?;
+                    let name = self
+                        .names
+                        .iter()
+                        .find(|(_, i)| **i == g)
+                        .map(|(n, _)| n.clone());
+                    self.nodes += 2;
+                    Ast::Group(g, name, Box::new(t))
+                }
+            };
+            if let Ast::Char(_) | Ast::Assert(_) | Ast::Unicode(_) = item {
+                self.nodes += item.size();
+            }
+            seq.push(item);
+        }
+    }
+}
+
+impl Ast {
+    fn concat(mut v: Vec<Ast>) -> Ast {
+        match v.len() {
+            1 => v.pop().unwrap(),
+            _ => Ast::Concat(v),
+        }
+    }
+
+    fn alt(mut v: Vec<Ast>) -> Ast {
+        match v.len() {
+            1 => v.pop().unwrap(),
+            _ => Ast::Alt(v),
+        }
+    }
+
+    /// number of nfa nodes, built for the tree
+    pub fn size(&self) -> usize {
+        match self {
+            Ast::Char(_) | Ast::Assert(_) => 2,
+            Ast::Unicode(set) => {
+                2 + set
+                    .utf8_sequences()
+                    .iter()
+                    .map(|s| s.len() - 1)
+                    .sum::<usize>()
+            }
+            Ast::Concat(v) => v.iter().fold(0, |n, t| n.saturating_add(t.size())),
+            Ast::Alt(v) => v.iter().fold(2 * v.len().saturating_sub(1), |n, t| {
+                n.saturating_add(t.size())
+            }),
+            Ast::Repeat(t, min, max) => {
+                let copies = max.unwrap_or(min.saturating_add(1)) as usize;
+                let loop_nodes = if max.is_none() { 2 } else { 0 };
+                t.size().saturating_mul(copies).saturating_add(loop_nodes)
+            }
+            Ast::Group(_, _, t) => t.size().saturating_add(2),
+        }
+    }
+
+    /// write the item, parenthesized, if it is a concatenation or an alternation
+    fn fmt_item(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Ast::Concat(_) | Ast::Alt(_) => write!(f, "({self})"),
+            _ => write!(f, "{self}"),
+        }
+    }
+}
+
+/// byte or ascii char, escaped if it is special within charsets or outside of them
+fn fmt_byte(f: &mut std::fmt::Formatter, c: u8, class: bool) -> std::fmt::Result {
+    let special: &[u8] = match class {
+        true => b"\\]-^[",
+        false => b"\\()|^$.*+?{[# ",
+    };
+    match c {
+        _ if special.contains(&c) => write!(f, "\\{}", c as char),
+        0x20..=0x7e => write!(f, "{}", c as char),
+        _ => write!(f, "\\x{c:02x}"),
+    }
+}
+
+/// char of utf-8 pattern
+fn fmt_scalar(f: &mut std::fmt::Formatter, c: u32, class: bool) -> std::fmt::Result {
+    match char::from_u32(c) {
+        _ if c < 0x80 => fmt_byte(f, c as u8, class),
+        Some(ch) if !ch.is_control() => write!(f, "{ch}"),
+        _ => write!(f, "\\u{{{c:x}}}"),
+    }
+}
+
+/// single char or [...] with ranges
+fn fmt_ranges(
+    f: &mut std::fmt::Formatter,
+    ranges: &[(u32, u32)],
+    fmt_char: fn(&mut std::fmt::Formatter, u32, bool) -> std::fmt::Result,
+) -> std::fmt::Result {
+    if let [(a, b)] = ranges {
+        if a == b {
+            return fmt_char(f, *a, false);
+        }
+    }
+    write!(f, "[")?;
+    for (a, b) in ranges {
+        fmt_char(f, *a, true)?;
+        if b > a {
+            if *b > a + 1 {
+                write!(f, "-")?;
+            }
+            fmt_char(f, *b, true)?;
+        }
+    }
+    write!(f, "]")
+}
+
+impl std::fmt::Display for Ast {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Ast::Char(set) => {
+                let mut ranges: Vec<(u32, u32)> = Vec::new();
+                for c in set.iter().map(|c| c as u32) {
+                    match ranges.last_mut() {
+                        Some((_, b)) if *b + 1 == c => *b = c,
+                        _ => ranges.push((c, c)),
+                    }
+                }
+                fmt_ranges(f, &ranges, |f, c, class| fmt_byte(f, c as u8, class))
+            }
+            Ast::Unicode(set) => fmt_ranges(f, set.ranges(), fmt_scalar),
+            Ast::Assert(x) => match x {
+                Assert::TextStart => write!(f, "^"),
+                Assert::TextEnd => write!(f, "$"),
+                Assert::LineStart => write!(f, "(?m:^)"),
+                Assert::LineEnd => write!(f, "(?m:$)"),
+                Assert::WordBoundary => write!(f, "\\b"),
+                Assert::NotWordBoundary => write!(f, "\\B"),
+            },
+            Ast::Concat(v) => v.iter().try_for_each(|t| t.fmt_item(f)),
+            Ast::Alt(v) => {
+                for (i, t) in v.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, "|")?;
+                    }
+                    match t {
+                        Ast::Alt(_) => write!(f, "({t})")?,
+                        _ => write!(f, "{t}")?,
+                    }
+                }
+                Ok(())
+            }
+            Ast::Repeat(t, min, max) => {
+                t.fmt_item(f)?;
+                match (min, max) {
+                    (0, None) => write!(f, "*"),
+                    (1, None) => write!(f, "+"),
+                    (0, Some(1)) => write!(f, "?"),
+                    (n, None) => write!(f, "{{{n},}}"),
+                    (0, Some(m)) => write!(f, "{{,{m}}}"),
+                    (n, Some(m)) if n == m => write!(f, "{{{n}}}"),
+                    (n, Some(m)) => write!(f, "{{{n},{m}}}"),
+                }
+            }
+            Ast::Group(_, Some(name), t) => write!(f, "(?<{name}>{t})"),
+            Ast::Group(_, None, t) => write!(f, "\\A({t})\\Z"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_ast {
+    use super::*;
+
+    fn ast(s: &[u8]) -> Ast {
+        build_ast(Lexer::new(s, Config::default())).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(ast(b"a"), Ast::Char(charset!(b'a')));
+        assert_eq!(
+            ast(b"\\A(ab)\\Z|c*"),
+            Ast::Alt(vec![
+                Ast::Group(
+                    0,
+                    None,
+                    Box::new(Ast::Concat(vec![
+                        Ast::Char(charset!(b'a')),
+                        Ast::Char(charset!(b'b'))
+                    ]))
+                ),
+                Ast::Repeat(Box::new(Ast::Char(charset!(b'c'))), 0, None),
+            ])
+        );
+        assert_eq!(
+            ast(b"(?<x>^)"),
+            Ast::Group(
+                0,
+                Some(String::from("x")),
+                Box::new(Ast::Assert(Assert::TextStart))
+            )
+        );
+        for s in [&b"a{2}{3}"[..], b"(a|b)*", b"\\A(a|bc|d)+\\Z", b"a{2,}b?"] {
+            let nfa = nfa_uncooked(Lexer::new(s, Config::default())).unwrap();
+            assert_eq!(ast(s).size(), nfa.nodes);
+        }
+    }
+
+    #[test]
+    fn display() {
+        for (s, ans) in [
+            (&b"abc"[..], "abc"),
+            (b"(ab)c|d", "(ab)c|d"),
+            (b"(a|b)*\\.", "(a|b)*\\."),
+            (b"a+b?c{2}d{2,}e{,3}f{2,3}", "a+b?c{2}d{2,}e{,3}f{2,3}"),
+            (b"\\A(a)\\Z(?<x>b)", "\\A(a)\\Z(?<x>b)"),
+            (b"[a-cx-y\\]]\\x00\\d", "[\\]a-cxy]\\x00[0-9]"),
+            (b"(?mi)^a$", "(?m:^)[Aa](?m:$)"),
+            (b"\\b \\B#", "\\b\\ \\B\\#"),
+        ] {
+            assert_eq!(ast(s).to_string(), ans);
+        }
+        let config = Config {
+            utf8: true,
+            ..Config::default()
+        };
+        let s = "é[а-я]".as_bytes();
+        assert_eq!(
+            build_ast(Lexer::new(s, config)).unwrap().to_string(),
+            "é[а-я]"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for s in [
+            &b"(a|b)*a(a|b){3}"[..],
+            b"((a|b)|c)(d(ef))",
+            b"\\A((\\A(a?)\\Z){2})\\Z",
+            b"[^a]+|.",
+            b"a**|(?s:.)",
+            b"\\A(x)\\Z*",
+        ] {
+            let t = ast(s);
+            assert_eq!(ast(t.to_string().as_bytes()), t);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let err = |s: &[u8]| build_ast(Lexer::new(s, Config::default())).unwrap_err();
+        assert_eq!(err(b"a|"), Error::Union.at(2..2));
+        assert_eq!(err(b"(a"), Error::Balance.at(2..2));
+        assert_eq!(err(b"*"), Error::Postfix.at(0..1));
+        assert_eq!(err(b"a\\Z"), Error::Group.at(1..3));
+        assert_eq!(err(b"a{255}{255}"), Error::TooLarge.at(6..11));
+    }
+}
@@ -1,86 +1,220 @@
 /*
  * Build DFA
+ *
+ * - run subset construction over byte classes
+ * - store transitions as a dense table: nodes[n][class] is the next state or DEAD
+ * - with assertions, a state is a set of nfa nodes and look behind, there is a start state for each look;
+ *   assertions are resolved on transition, when look ahead is known
+ * - states are numbered by `Subsets`, that tagged dfa and lazy dfa use too
  */
 
 use std::collections::VecDeque;
 
+pub const DEAD: usize = usize::MAX;
+
 pub struct DFA {
-    nodes: Vec<HashMap<u8, usize>>,
-    head: Vec<HashSet<usize>>,
-    tail: Vec<HashSet<usize>>,
+    pub classes: ByteClasses,
+    pub nodes: Vec<Vec<usize>>,
+    /// start state for each look behind
+    pub start: Vec<usize>,
+    pub head: Vec<HashSet<usize>>,
+    pub tail: Vec<HashSet<usize>>,
+    /// ids of rules, accepted in each state, for each look ahead
+    pub accept: Vec<Vec<HashSet<usize>>>,
+}
+
+/// next state after byte `c` in a dense table over `classes`, shared with compiled regexes and scanners
+pub fn next_state(classes: &ByteClasses, nodes: &[Vec<usize>], n: usize, c: u8) -> Option<usize> {
+    match nodes[n][classes.get(c)] {
+        DEAD => None,
+        s => Some(s),
+    }
+}
+
+/// ids of subset construction states: kernels of nfa nodes with look behind, numbered in order of creation
+pub struct Subsets {
+    /// without assertions, look behind does not matter
+    asserts: bool,
+    /// ids[behind][kernel] is the id of a state
+    ids: Vec<HashMap<Vec<usize>, usize>>,
+    len: usize,
+}
+
+impl Subsets {
+    pub fn new(asserts: bool) -> Subsets {
+        Subsets {
+            asserts,
+            ids: vec![HashMap::new(); LOOKS],
+            len: 0,
+        }
+    }
+
+    /// look behind of a state, entered after a byte with look `look`
+    pub fn behind(&self, look: usize) -> usize {
+        match self.asserts {
+            true => look,
+            false => LOOK_EDGE,
+        }
+    }
+
+    /// id of the state with `kernel`, entered after a byte with look `look`, if it exists
+    pub fn get(&self, kernel: &[usize], look: usize) -> Option<usize> {
+        self.ids[self.behind(look)].get(kernel).copied()
+    }
+
+    /// add a new state, its id is the number of states before it
+    pub fn insert(&mut self, kernel: Vec<usize>, look: usize) -> usize {
+        let behind = self.behind(look);
+        self.ids[behind].insert(kernel, self.len);
+        self.len += 1;
+        self.len - 1
+    }
 }
 
 pub fn build_dfa(nfa: NFA) -> DFA {
+    build_dfa_limited(nfa, usize::MAX).unwrap()
+}
+
+/// build dfa, unless it gets more than `limit` states
+pub fn build_dfa_limited(nfa: NFA, limit: usize) -> Option<DFA> {
     let mut dfa = DFA {
+        classes: nfa.classes.clone(),
         nodes: vec![],
+        start: vec![],
         head: vec![],
         tail: vec![],
+        accept: vec![],
     };
-    dfa.init_from_nfa(nfa);
-    return dfa;
+    dfa.init_from_nfa(nfa, limit).then_some(dfa)
 }
 
 impl DFA {
-    fn node(&mut self, nfa: &NFA, out: &mut Vec<HashSet<usize>>, origin: HashSet<usize>) -> usize {
+    /// dfa without states
+    pub fn empty(classes: ByteClasses) -> DFA {
+        DFA {
+            classes,
+            nodes: vec![],
+            start: vec![],
+            head: vec![],
+            tail: vec![],
+            accept: vec![],
+        }
+    }
+
+    pub fn next(&self, n: usize, c: u8) -> Option<usize> {
+        next_state(&self.classes, &self.nodes, n, c)
+    }
+
+    fn node(
+        &mut self,
+        nfa: &NFA,
+        out: &mut Vec<(HashSet<usize>, usize)>,
+        origin: (HashSet<usize>, usize),
+    ) -> usize {
         let node = self.nodes.len();
-        self.nodes.push(HashMap::new());
+        self.nodes.push(vec![DEAD; self.classes.count]);
         let mut head: HashSet<usize> = HashSet::new();
         let mut tail: HashSet<usize> = HashSet::new();
-        for n in origin.iter() {
-            head.extend(&nfa.nodes[*n].head);
-            tail.extend(&nfa.nodes[*n].tail);
+        let mut accept: Vec<HashSet<usize>> = vec![HashSet::new(); LOOKS];
+        for (look, accept) in accept.iter_mut().enumerate() {
+            for n in nfa.closure(&origin.0, origin.1, look).iter() {
+                head.extend(&nfa.nodes[*n].head);
+                tail.extend(&nfa.nodes[*n].tail);
+                accept.extend(&nfa.nodes[*n].accept);
+            }
         }
         self.head.push(head);
         self.tail.push(tail);
+        self.accept.push(accept);
         out.push(origin);
         return node;
     }
 
-    fn init_from_nfa(&mut self, nfa: NFA) {
-        let mut output: Vec<HashSet<usize>> = Vec::new();
-        let mut queue: VecDeque<usize> = VecDeque::new(); // index within output
-        queue.push_back(self.node(&nfa, &mut output, HashSet::from([nfa.begin])));
+    /// find state by its set and look ahead, that leads to it, or create a new one
+    fn state(
+        &mut self,
+        nfa: &NFA,
+        subsets: &mut Subsets,
+        out: &mut Vec<(HashSet<usize>, usize)>,
+        queue: &mut VecDeque<usize>,
+        set: HashSet<usize>,
+        look: usize,
+    ) -> usize {
+        let kernel = sorted(&set);
+        if let Some(id) = subsets.get(&kernel, look) {
+            return id;
+        }
+        let id = subsets.insert(kernel, look);
+        self.node(nfa, out, (set, subsets.behind(look)));
+        queue.push_back(id);
+        id
+    }
 
-        while queue.len() != 0 {
-            let id_from = queue.pop_front().unwrap();
+    fn init_from_nfa(&mut self, nfa: NFA, limit: usize) -> bool {
+        let mut subsets = Subsets::new(nfa.has_asserts());
+        let mut output: Vec<(HashSet<usize>, usize)> = Vec::new();
+        let mut queue: VecDeque<usize> = VecDeque::new(); // index within output
+        for look in 0..LOOKS {
+            let id = self.state(
+                &nfa,
+                &mut subsets,
+                &mut output,
+                &mut queue,
+                HashSet::from([nfa.begin]),
+                look,
+            );
+            self.start.push(id);
+        }
 
-            for c in Charset::ALL.iter() {
-                let from_set = &output[id_from];
+        while let Some(id_from) = queue.pop_front() {
+            if self.nodes.len() > limit {
+                return false;
+            }
+            let (set, prev) = &output[id_from];
+            let from: Vec<HashSet<usize>> =
+                (0..LOOKS).map(|l| nfa.closure(set, *prev, l)).collect();
+            for (class, c) in self.classes.reps().into_iter().enumerate() {
+                let look = self.classes.look[class];
                 let mut to: HashSet<usize> = HashSet::new();
-                for from in from_set {
-                    match nfa.nodes[*from].edges.get(&c) {
+                for n in from[look].iter() {
+                    match nfa.nodes[*n].edges.get(&c) {
                         None => (),
                         Some(s) => to.extend(s),
                     }
                 }
 
-                if to.len() == 0 {
+                if to.is_empty() {
                     continue;
                 }
 
-                match output.iter().position(|s| *s == to) {
-                    Some(id_to) => _ = self.nodes[id_from].insert(c, id_to),
-                    None => {
-                        let id_to = self.node(&nfa, &mut output, to);
-                        queue.push_back(id_to);
-                        self.nodes[id_from].insert(c, id_to);
-                    }
-                }
+                self.nodes[id_from][class] =
+                    self.state(&nfa, &mut subsets, &mut output, &mut queue, to, look);
             }
         }
+        self.nodes.len() <= limit
     }
 }
 
 impl Automation for DFA {
-    fn nodes(&self) -> impl Iterator<Item = (HashSet<usize>, HashSet<usize>)> {
-        (0..self.nodes.len()).map(|n| (self.head[n].clone(), self.tail[n].clone()))
+    fn nodes(&self) -> impl Iterator<Item = (HashSet<usize>, HashSet<usize>, bool)> {
+        (0..self.nodes.len()).map(|n| {
+            (
+                self.head[n].clone(),
+                self.tail[n].clone(),
+                self.accept[n].iter().any(|s| !s.is_empty()),
+            )
+        })
     }
 
     fn edges(&self) -> impl Iterator<Item = (usize, usize, Option<u8>)> {
-        self.nodes
-            .iter()
-            .enumerate()
-            .flat_map(|(a, n)| n.iter().map(move |(c, b)| (a, *b, Some(*c))))
+        self.nodes.iter().enumerate().flat_map(move |(a, n)| {
+            n.iter()
+                .enumerate()
+                .filter(|(_, b)| **b != DEAD)
+                .flat_map(move |(class, b)| {
+                    self.classes.bytes(class).map(move |c| (a, *b, Some(c)))
+                })
+        })
     }
 }
 
@@ -92,7 +226,63 @@ mod test_dfa {
     fn just_works() {
         let nfa = build_dfa(build_nfa(Lexer::new(b"a", Config::default())).unwrap());
         assert_eq!(nfa.nodes.len(), 2);
-        assert_eq!(nfa.nodes[0], HashMap::from([(b'a', 1)]));
-        assert_eq!(nfa.nodes[1], HashMap::new());
+        assert_eq!(nfa.classes.count, 2);
+        assert_eq!(nfa.nodes, vec![vec![DEAD, 1], vec![DEAD, DEAD]]);
+        assert_eq!(nfa.next(0, b'a'), Some(1));
+        assert_eq!(nfa.next(0, b'b'), None);
+        assert_eq!(nfa.start, vec![0; LOOKS]);
+        assert_eq!(nfa.accept[0], vec![HashSet::new(); LOOKS]);
+        assert_eq!(nfa.accept[1], vec![HashSet::from([0]); LOOKS]);
+
+        let dfa = build_dfa(build_nfa(Lexer::new(b"a*b?", Config::default())).unwrap());
+        assert_eq!(
+            dfa.accept,
+            vec![vec![HashSet::from([0]); LOOKS]; dfa.nodes.len()]
+        );
+
+        let dfa = build_dfa(build_nfa(Lexer::new(b"[a-z]+|x\\d", Config::default())).unwrap());
+        assert_eq!(dfa.classes.count, 4);
+        assert_eq!(dfa.nodes.len(), 5);
+        let x = dfa.next(0, b'x').unwrap();
+        assert_eq!(
+            dfa.next(x, b'5').map(|n| dfa.accept[n][LOOK_EDGE].len()),
+            Some(1)
+        );
+        assert_eq!(
+            dfa.next(x, b'q'),
+            dfa.next(dfa.next(0, b'q').unwrap(), b'q')
+        );
+    }
+
+    #[test]
+    fn subsets() {
+        let mut plain = Subsets::new(false);
+        assert_eq!(plain.insert(vec![0], LOOK_NEWLINE), 0);
+        assert_eq!(plain.get(&[0], LOOK_OTHER), Some(0));
+        assert_eq!(plain.insert(vec![0, 1], LOOK_OTHER), 1);
+        let mut asserts = Subsets::new(true);
+        asserts.insert(vec![0], LOOK_NEWLINE);
+        assert_eq!(asserts.get(&[0], LOOK_OTHER), None);
+        assert_eq!(asserts.behind(LOOK_OTHER), LOOK_OTHER);
+    }
+
+    #[test]
+    fn anchors() {
+        let dfa = build_dfa(build_nfa(Lexer::new(b"^a$", Config::default())).unwrap());
+        assert_eq!(dfa.classes.count, 3);
+        let a = dfa.next(dfa.start[LOOK_EDGE], b'a').unwrap();
+        assert_eq!(dfa.accept[a][LOOK_EDGE], HashSet::from([0]));
+        assert_eq!(dfa.accept[a][LOOK_NEWLINE], HashSet::new());
+        assert_eq!(dfa.next(dfa.start[LOOK_OTHER], b'a'), None);
+
+        let config = Config {
+            multiline: true,
+            ..Config::default()
+        };
+        let dfa = build_dfa(build_nfa(Lexer::new(b"^a$", config)).unwrap());
+        let a = dfa.next(dfa.start[LOOK_NEWLINE], b'a').unwrap();
+        assert_eq!(dfa.accept[a][LOOK_NEWLINE], HashSet::from([0]));
+        assert_eq!(dfa.accept[a][LOOK_OTHER], HashSet::new());
+        assert_eq!(dfa.next(dfa.start[LOOK_OTHER], b'a'), None);
     }
 }
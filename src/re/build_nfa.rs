@@ -5,26 +5,33 @@ include!("build_nfa_uncooked.rs");
  *
  * - convert list of edges to an automation
  * - resolve epsilon closures
- * - propagate groups heads and tails
+ * - propagate groups heads and tails, accept flags and assertions
+ * - split bytes into equivalence classes
  */
 
 #[derive(Clone, Debug)]
 pub struct NFANode {
     edges: HashMap<u8, HashSet<usize>>,
+    /// nodes, reachable without reading a byte, if assertion holds
+    asserts: HashSet<(Assert, usize)>,
     head: HashSet<usize>,
     tail: HashSet<usize>,
+    accept: HashSet<usize>,
 }
 
 pub struct NFA {
     nodes: Vec<NFANode>,
     begin: usize,
+    classes: ByteClasses,
 }
 
-pub fn build_nfa(lex: Lexer) -> Result<NFA> {
-    let uncooked = nfa_uncooked(lex)?;
-    let (mut nfa, eps, edges) = NFA::from(uncooked);
-    nfa.process(eps, edges);
-    Ok(nfa)
+pub fn build_nfa(lex: Lexer) -> std::result::Result<NFA, Diagnostic> {
+    Ok(NFA::cook(nfa_uncooked(lex)?))
+}
+
+/// build nfa for several patterns, nodes accept with ids of rules
+pub fn build_nfa_rules(rules: Vec<Lexer>) -> std::result::Result<NFA, (usize, Diagnostic)> {
+    Ok(NFA::cook(nfa_uncooked_rules(rules)?))
 }
 
 fn mut_pair<T>(v: &mut [T], i: usize, j: usize) -> (&mut T, &mut T) {
@@ -38,27 +45,39 @@ fn mut_pair<T>(v: &mut [T], i: usize, j: usize) -> (&mut T, &mut T) {
 }
 
 impl NFA {
+    fn cook(uncooked: NFAUncooked) -> NFA {
+        let (mut nfa, eps, edges) = NFA::from(uncooked);
+        nfa.process(eps, edges);
+        nfa
+    }
+
     fn from(nfa: NFAUncooked) -> (NFA, Vec<HashSet<usize>>, Vec<Vec<Charset>>) {
         let mut res = NFA {
             nodes: vec![
                 NFANode {
                     edges: HashMap::new(),
+                    asserts: HashSet::new(),
                     head: HashSet::new(),
                     tail: HashSet::new(),
+                    accept: HashSet::new(),
                 };
                 nfa.nodes
             ],
             begin: nfa.begin,
+            classes: nfa.classes(),
         };
         let mut eps: Vec<HashSet<usize>> = vec![HashSet::new(); nfa.nodes];
         let mut edges: Vec<Vec<Charset>> = vec![vec![charset!(); nfa.nodes]; nfa.nodes];
         for (a, b) in nfa.eps_edges {
             eps[a].insert(b);
         }
+        for (a, b, x) in nfa.asserts {
+            res.nodes[a].asserts.insert((x, b));
+        }
         for (a, b, s) in nfa.edges {
             edges[a][b] = s.clone();
             for c in s.iter() {
-                let n = res.nodes[a].edges.entry(c).or_insert(HashSet::new());
+                let n = res.nodes[a].edges.entry(c).or_default();
                 n.insert(b);
             }
         }
@@ -68,6 +87,9 @@ impl NFA {
         for (n, g) in nfa.tail {
             res.nodes[n].tail.insert(g);
         }
+        for (n, r) in nfa.accept {
+            res.nodes[n].accept.insert(r);
+        }
         return (res, eps, edges);
     }
 
@@ -96,21 +118,44 @@ impl NFA {
             let (np, ni) = mut_pair(&mut self.nodes, n, i);
             np.head.extend(&ni.head);
             np.tail.extend(&ni.tail);
+            np.accept.extend(&ni.accept);
+            np.asserts.extend(&ni.asserts);
         }
-        for i in 0..self.nodes.len() {
-            for c in edges[n][i].iter() {
-                let s = self.nodes[p].edges.entry(c).or_insert(HashSet::new());
+        for (i, s) in edges[n].iter().enumerate().filter(|(_, s)| !s.empty()) {
+            for c in s.iter() {
+                let s = self.nodes[p].edges.entry(c).or_default();
                 s.insert(i);
             }
         }
     }
+
+    /// nodes, reachable from `set` through assertions, that hold between looks `prev` and `next`
+    fn closure(&self, set: &HashSet<usize>, prev: usize, next: usize) -> HashSet<usize> {
+        let mut res = set.clone();
+        let mut stack: Vec<usize> = set.iter().copied().collect();
+        while let Some(n) = stack.pop() {
+            for (x, m) in self.nodes[n].asserts.iter() {
+                if x.check(prev, next) && res.insert(*m) {
+                    stack.push(*m);
+                }
+            }
+        }
+        res
+    }
+
+    fn has_asserts(&self) -> bool {
+        self.nodes.iter().any(|n| !n.asserts.is_empty())
+    }
 }
 
 impl Automation for NFA {
-    fn nodes(&self) -> impl Iterator<Item = (HashSet<usize>, HashSet<usize>)> {
+    fn nodes(&self) -> impl Iterator<Item = (HashSet<usize>, HashSet<usize>, bool)> {
         let s: HashSet<usize> = HashSet::new();
-        std::iter::once((s.clone(), s.clone()))
-            .chain(self.nodes.iter().map(|n| (n.head.clone(), n.tail.clone())))
+        std::iter::once((s.clone(), s.clone(), false)).chain(
+            self.nodes
+                .iter()
+                .map(|n| (n.head.clone(), n.tail.clone(), !n.accept.is_empty())),
+        )
     }
 
     fn edges(&self) -> impl Iterator<Item = (usize, usize, Option<u8>)> {
@@ -155,13 +200,15 @@ mod test_nfa {
         assert_eq!(nfa.nodes[1].head, HashSet::new());
         assert_eq!(nfa.nodes[0].tail, HashSet::new());
         assert_eq!(nfa.nodes[1].tail, HashSet::new());
+        assert_eq!(nfa.nodes[0].accept, HashSet::new());
+        assert_eq!(nfa.nodes[1].accept, HashSet::from([0]));
     }
 
     fn merge<T: Iterator<Item = HashSet<usize>>>(it: T) -> HashMap<usize, HashSet<usize>> {
         let mut r: HashMap<usize, HashSet<usize>> = HashMap::new();
         for (i, e) in it.enumerate() {
             if !e.is_empty() {
-                r.entry(i).or_insert(HashSet::new()).extend(e);
+                r.entry(i).or_default().extend(e);
             }
         }
         return r;
@@ -179,7 +226,7 @@ mod test_nfa {
         assert_eq!(nfa.nodes.len(), 10);
         assert_eq!(nfa.begin, 8);
         assert_eq!(
-            merge(nfa.nodes.iter().filter_map(|x| Some(x.head.clone()))),
+            merge(nfa.nodes.iter().map(|x| x.head.clone())),
             HashMap::from([
                 (8, HashSet::from([0, 1])),
                 (2, HashSet::from([0])),
@@ -187,7 +234,7 @@ mod test_nfa {
             ]),
         );
         assert_eq!(
-            merge(nfa.nodes.iter().filter_map(|x| Some(x.tail.clone()))),
+            merge(nfa.nodes.iter().map(|x| x.tail.clone())),
             HashMap::from([
                 (1, HashSet::from([0])),
                 (5, HashSet::from([1])),
@@ -201,6 +248,10 @@ mod test_nfa {
         );
         assert_eq!(nfa.nodes[1].edges, HashMap::from([]));
         assert_eq!(nfa.nodes[5].edges, HashMap::from([]));
+        assert_eq!(
+            HashSet::from_iter((0..nfa.nodes.len()).filter(|i| !nfa.nodes[*i].accept.is_empty())),
+            HashSet::from([1, 3, 5, 7, 9])
+        );
 
         let nfa = build_nfa(Lexer::new(b"((a|b)c((d)))", Config::default())).unwrap();
         /*
@@ -211,11 +262,11 @@ mod test_nfa {
         assert_eq!(nfa.nodes.len(), 10);
         assert_eq!(nfa.begin, 4);
         assert_eq!(
-            merge(nfa.nodes.iter().filter_map(|x| Some(x.tail.clone()))),
+            merge(nfa.nodes.iter().map(|x| x.tail.clone())),
             HashMap::new()
         );
         assert_eq!(
-            merge(nfa.nodes.iter().filter_map(|x| Some(x.head.clone()))),
+            merge(nfa.nodes.iter().map(|x| x.head.clone())),
             HashMap::new()
         );
         assert_eq!(
@@ -235,4 +286,25 @@ mod test_nfa {
             HashMap::from([(b'd', HashSet::from([9]))])
         );
     }
+
+    #[test]
+    fn anchors() {
+        let nfa = build_nfa(Lexer::new(b"^a", Config::default())).unwrap();
+        // 0 -^- 1 - 2 -a- 3
+        assert_eq!(
+            nfa.nodes[0].asserts,
+            HashSet::from([(Assert::TextStart, 1)])
+        );
+        assert_eq!(
+            nfa.nodes[1].edges,
+            HashMap::from([(b'a', HashSet::from([3]))])
+        );
+        assert_eq!(nfa.classes.count, 3);
+        let begin = HashSet::from([0]);
+        assert_eq!(
+            nfa.closure(&begin, LOOK_EDGE, LOOK_OTHER),
+            HashSet::from([0, 1])
+        );
+        assert_eq!(nfa.closure(&begin, LOOK_NEWLINE, LOOK_OTHER), begin);
+    }
 }
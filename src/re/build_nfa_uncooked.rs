@@ -1,54 +1,157 @@
 /*
  * Build NFA (first stage)
  *
- * Run Thompson algorithm
+ * Run Thompson algorithm over the AST of each rule
  * - NFA is stored as a list of edges
  * - for each group, nfa.head[n] = g, if n starts group g, nfa.tail[n] = g, if end
+ * - nfa.names[name] = g for named groups
+ * - nfa.accept[n] = r, if n is the end of rule r (each pattern is a rule, rules are united)
+ * - anchors are edges with assertions, they are passed without reading a byte
+ * - parser fails with Error::TooLarge, once nodes exceed Config::max_nfa_nodes, before repeats are expanded
  */
 
 use std::collections::HashMap;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct NFAUncooked {
     nodes: usize,
     groups: usize,
+    names: HashMap<String, usize>,
     begin: usize,
     edges: Vec<(usize, usize, Charset)>,
     eps_edges: Vec<(usize, usize)>,
+    asserts: Vec<(usize, usize, Assert)>,
+    /// word chars, if word assertions are used
+    word: Option<Charset>,
     head: HashMap<usize, usize>,
     tail: HashMap<usize, usize>,
+    accept: HashMap<usize, usize>,
 }
 
-pub fn nfa_uncooked(mut lex: Lexer) -> Result<NFAUncooked> {
-    let mut nfa = NFAUncooked {
-        nodes: 0,
-        groups: 0,
-        begin: 0,
-        edges: Vec::new(),
-        eps_edges: Vec::new(),
-        head: HashMap::new(),
-        tail: HashMap::new(),
+pub fn nfa_uncooked(lex: Lexer) -> std::result::Result<NFAUncooked, Diagnostic> {
+    nfa_uncooked_ast(lex).map(|(nfa, _)| nfa)
+}
+
+/// nfa and the tree, it is built from
+fn nfa_uncooked_ast(lex: Lexer) -> std::result::Result<(NFAUncooked, Ast), Diagnostic> {
+    let (nfa, mut asts) = nfa_asts(vec![lex]).map_err(|(_, e)| e)?;
+    Ok((nfa, asts.pop().unwrap()))
+}
+
+/// compile several patterns into one automation, rule id is an index within `rules`
+/// on failure, returns id of the failed rule
+pub fn nfa_uncooked_rules(
+    rules: Vec<Lexer>,
+) -> std::result::Result<NFAUncooked, (usize, Diagnostic)> {
+    nfa_asts(rules).map(|(nfa, _)| nfa)
+}
+
+fn nfa_asts(
+    rules: Vec<Lexer>,
+) -> std::result::Result<(NFAUncooked, Vec<Ast>), (usize, Diagnostic)> {
+    let mut nfa = NFAUncooked::new();
+    let mut parser = AstParser::default();
+    let mut begins: Vec<usize> = Vec::new();
+    let mut asts: Vec<Ast> = Vec::new();
+    for (r, mut lex) in rules.into_iter().enumerate() {
+        let ast = parser
+            .parse(&mut lex, 0)
+            .map_err(|e| (r, e.at(lex.span())))?;
+        let (begin, end, _) = nfa.build(&ast, &lex.config);
+        nfa.accept.insert(end, r);
+        begins.push(begin);
+        asts.push(ast);
+    }
+    nfa.groups = parser.groups;
+    nfa.names = parser.names;
+    nfa.begin = match begins[..] {
+        [begin] => begin,
+        _ => {
+            let a = nfa.node();
+            for b in begins {
+                nfa.eps_edges.push((a, b));
+            }
+            a
+        }
     };
-    nfa.begin = nfa.compile(&mut lex, 0)?.0;
-    Ok(nfa)
+    Ok((nfa, asts))
 }
 
 impl NFAUncooked {
+    /// automation without nodes
+    fn new() -> NFAUncooked {
+        NFAUncooked {
+            nodes: 0,
+            groups: 0,
+            names: HashMap::new(),
+            begin: 0,
+            edges: Vec::new(),
+            eps_edges: Vec::new(),
+            asserts: Vec::new(),
+            word: None,
+            head: HashMap::new(),
+            tail: HashMap::new(),
+            accept: HashMap::new(),
+        }
+    }
+
+    /// automation for reversed input, accepting at the beginning of each rule
+    /// groups are dropped; if unanchored, it may start anywhere after the end of a match
+    fn reverse(&self, unanchored: bool) -> NFAUncooked {
+        let mut nfa = NFAUncooked {
+            nodes: self.nodes + 1,
+            groups: 0,
+            names: HashMap::new(),
+            begin: self.nodes,
+            edges: self
+                .edges
+                .iter()
+                .map(|(a, b, s)| (*b, *a, s.clone()))
+                .collect(),
+            eps_edges: self.eps_edges.iter().map(|(a, b)| (*b, *a)).collect(),
+            asserts: self
+                .asserts
+                .iter()
+                .map(|(a, b, x)| (*b, *a, x.reverse()))
+                .collect(),
+            word: self.word.clone(),
+            head: HashMap::new(),
+            tail: HashMap::new(),
+            accept: HashMap::from([(self.begin, 0)]),
+        };
+        for n in self.accept.keys() {
+            nfa.eps_edges.push((nfa.begin, *n));
+        }
+        if unanchored {
+            nfa.edges.push((nfa.begin, nfa.begin, Charset::ALL));
+        }
+        nfa
+    }
+
+    /// byte classes, that tell apart all charsets and looks of the nfa
+    fn classes(&self) -> ByteClasses {
+        let mut looks = match self.asserts.is_empty() {
+            true => vec![],
+            false => vec![charset!(b'\n')],
+        };
+        looks.extend(self.word.clone());
+        ByteClasses::new(
+            self.edges.iter().map(|(_, _, s)| s).chain(looks.iter()),
+            self.word.as_ref(),
+        )
+    }
+
     fn node(&mut self) -> usize {
         self.nodes += 1;
         return self.nodes - 1;
     }
 
-    fn join(&mut self, queue: &mut Vec<(usize, usize, usize)>, last: usize) -> Result<()> {
-        if last == queue.len() {
-            return Err(Error::Union);
-        }
-        while last < queue.len() - 1 {
+    fn join(&mut self, queue: &mut Vec<(usize, usize, usize)>, last: usize) {
+        while last + 1 < queue.len() {
             let (b, a) = (queue.pop().unwrap(), queue.pop().unwrap());
             self.eps_edges.push((a.1, b.0));
             queue.push((a.0, b.1, a.2 + b.2));
         }
-        Ok(())
     }
 
     fn union(&mut self, queue: &mut Vec<(usize, usize, usize)>) {
@@ -67,20 +170,31 @@ impl NFAUncooked {
         let size = p.2;
         let origin = self.nodes - size;
         self.nodes += size;
-        for i in (0..self.edges.len()).rev() {
+        // edges are copied in their order, as it defines priority of paths
+        let first = self
+            .edges
+            .iter()
+            .rposition(|(a, b, _)| *a < origin && *b < origin);
+        for i in first.map_or(0, |i| i + 1)..self.edges.len() {
             let (a, b, c) = self.edges[i].clone();
-            if a < origin && b < origin {
-                break;
-            }
             self.edges.push((a + size, b + size, c));
         }
-        for i in (0..self.eps_edges.len()).rev() {
+        let first = self
+            .eps_edges
+            .iter()
+            .rposition(|(a, b)| *a < origin || *b < origin);
+        for i in first.map_or(0, |i| i + 1)..self.eps_edges.len() {
             let (a, b) = self.eps_edges[i];
-            if a < origin || b < origin {
-                break;
-            }
             self.eps_edges.push((a + size, b + size));
         }
+        let first = self
+            .asserts
+            .iter()
+            .rposition(|(a, b, _)| *a < origin || *b < origin);
+        for i in first.map_or(0, |i| i + 1)..self.asserts.len() {
+            let (a, b, x) = self.asserts[i];
+            self.asserts.push((a + size, b + size, x));
+        }
         for i in origin..self.nodes - size {
             match self.head.get(&i) {
                 Some(g) => _ = self.head.insert(i + size, *g),
@@ -94,90 +208,112 @@ impl NFAUncooked {
         return (p.0 + size, p.1 + size, size);
     }
 
-    fn compile(&mut self, lex: &mut Lexer, scope: usize) -> Result<(usize, usize, usize)> {
-        let mut groups: Vec<usize> = Vec::new();
-        let mut queue: Vec<(usize, usize, usize)> = Vec::new();
-        let mut last_union = 0;
-        loop {
-            match lex.token()? {
-                Token::Close(eof) => {
-                    if (scope == 0) != eof {
-                        return Err(Error::Balance);
-                    }
-                    self.join(&mut queue, last_union)?;
-                    self.union(&mut queue);
-                    return queue.pop().ok_or(Error::Empty);
-                }
-
-                Token::Open => queue.push(self.compile(lex, scope + 1)?),
+    /// fragment of the tree: (begin, end, number of nodes)
+    fn build(&mut self, ast: &Ast, config: &Config) -> (usize, usize, usize) {
+        match ast {
+            Ast::Char(charset) => {
+                let (a, b) = (self.node(), self.node());
+                self.edges.push((a, b, charset.clone()));
+                (a, b, 2)
+            }
 
-                Token::Repeat((min, max)) => {
-                    if last_union == queue.len() {
-                        return Err(Error::Postfix);
-                    }
-                    let max_bound = max.unwrap_or(min + 1);
-                    let mut a = queue.last().ok_or(Error::Group)?.clone();
-                    for i in 0..max_bound {
-                        if i == min {
-                            self.eps_edges.push((a.0, a.1));
-                        }
-                        if i < max_bound - 1 {
-                            let b = self.copy_last(a);
-                            queue.push(b);
-                            a = b;
-                        }
-                    }
-                    if max == None {
-                        let (a, b, p) = (self.node(), self.node(), queue.pop().unwrap());
-                        self.eps_edges.push((a, p.0));
-                        self.eps_edges.push((p.1, b));
-                        self.eps_edges.push((b, a));
-                        queue.push((a, b, p.2 + 2));
-                    }
+            Ast::Assert(x) => {
+                if x.word() {
+                    self.word = config.esc_charset.get(&b'w').cloned();
                 }
+                let (a, b) = (self.node(), self.node());
+                self.asserts.push((a, b, *x));
+                (a, b, 2)
+            }
 
-                Token::Char(charset) => {
-                    let (a, b) = (self.node(), self.node());
-                    self.edges.push((a, b, charset));
-                    queue.push((a, b, 2));
+            Ast::Unicode(set) => {
+                let (a, b) = (self.node(), self.node());
+                let mut size = 2;
+                for seq in set.utf8_sequences() {
+                    let mut from = a;
+                    for (i, (lo, hi)) in seq.iter().enumerate() {
+                        let to = match i == seq.len() - 1 {
+                            true => b,
+                            false => {
+                                size += 1;
+                                self.node()
+                            }
+                        };
+                        self.edges.push((from, to, charset!([*lo, *hi])));
+                        from = to;
+                    }
                 }
+                (a, b, size)
+            }
 
-                Token::Union => {
-                    self.join(&mut queue, last_union)?;
-                    last_union = queue.len();
-                }
+            Ast::Concat(v) => {
+                let mut queue: Vec<(usize, usize, usize)> =
+                    v.iter().map(|t| self.build(t, config)).collect();
+                self.join(&mut queue, 0);
+                queue[0]
+            }
 
-                Token::StartGroup => {
-                    groups.push(self.groups);
-                    self.groups += 1;
-                }
+            Ast::Alt(v) => {
+                let mut queue: Vec<(usize, usize, usize)> =
+                    v.iter().map(|t| self.build(t, config)).collect();
+                self.union(&mut queue);
+                queue[0]
+            }
 
-                Token::EndGroup => {
-                    if queue.len() == last_union {
-                        return Err(Error::Postfix);
+            Ast::Repeat(t, min, max) => {
+                let (min, max) = (*min, *max);
+                let max_bound = max.unwrap_or(min.saturating_add(1));
+                let mut a = self.build(t, config);
+                let mut queue = vec![a];
+                for i in 0..max_bound {
+                    if i == min {
+                        self.eps_edges.push((a.0, a.1));
+                    }
+                    if i < max_bound - 1 {
+                        let b = self.copy_last(a);
+                        queue.push(b);
+                        a = b;
                     }
-                    let p = queue.pop().unwrap();
-                    let (a, b, g) = (self.node(), self.node(), groups.pop().ok_or(Error::Group)?);
-                    self.head.insert(a, g);
-                    self.tail.insert(b, g);
+                }
+                if max.is_none() {
+                    let (a, b, p) = (self.node(), self.node(), queue.pop().unwrap());
                     self.eps_edges.push((a, p.0));
                     self.eps_edges.push((p.1, b));
+                    self.eps_edges.push((b, a));
                     queue.push((a, b, p.2 + 2));
                 }
+                // copies are a single fragment for groups and further repeats
+                self.join(&mut queue, 0);
+                queue[0]
+            }
+
+            Ast::Group(g, _, t) => {
+                let p = self.build(t, config);
+                let (a, b) = (self.node(), self.node());
+                self.head.insert(a, *g);
+                self.tail.insert(b, *g);
+                self.eps_edges.push((a, p.0));
+                self.eps_edges.push((p.1, b));
+                (a, b, p.2 + 2)
             }
         }
     }
 }
 
 impl Automation for NFAUncooked {
-    fn nodes(&self) -> impl Iterator<Item = (HashSet<usize>, HashSet<usize>)> {
+    fn nodes(&self) -> impl Iterator<Item = (HashSet<usize>, HashSet<usize>, bool)> {
         let g = |s: &HashMap<usize, usize>, i| match s.get(&i) {
             None => HashSet::new(),
             Some(c) => HashSet::from([*c]),
         };
         let s: HashSet<usize> = HashSet::new();
-        std::iter::once((s.clone(), s.clone()))
-            .chain((0..self.nodes).map(move |i| (g(&self.head, i), g(&self.tail, i))))
+        std::iter::once((s.clone(), s.clone(), false)).chain((0..self.nodes).map(move |i| {
+            (
+                g(&self.head, i),
+                g(&self.tail, i),
+                self.accept.contains_key(&i),
+            )
+        }))
     }
 
     fn edges(&self) -> impl Iterator<Item = (usize, usize, Option<u8>)> {
@@ -194,7 +330,7 @@ mod test_nfa_uncooked {
     use super::*;
     use std::collections::HashSet;
 
-    fn lexer(s: &[u8]) -> Lexer {
+    fn lexer(s: &[u8]) -> Lexer<'_> {
         return Lexer::new(s, Config::default());
     }
 
@@ -208,6 +344,7 @@ mod test_nfa_uncooked {
          */
         assert_eq!(nfa.nodes, 10);
         assert_eq!(nfa.begin, 8);
+        assert_eq!(nfa.accept, HashMap::from([(9, 0)]));
         assert_eq!(nfa.groups, 2);
         assert_eq!(nfa.head, HashMap::from([(2, 0), (6, 1)]));
         assert_eq!(nfa.tail, HashMap::from([(3, 0), (7, 1)]));
@@ -237,6 +374,7 @@ mod test_nfa_uncooked {
          */
         assert_eq!(nfa.nodes, 10);
         assert_eq!(nfa.begin, 4);
+        assert_eq!(nfa.accept, HashMap::from([(9, 0)]));
         assert_eq!(nfa.head, HashMap::new());
         assert_eq!(nfa.tail, HashMap::new());
         assert_eq!(
@@ -273,6 +411,14 @@ mod test_nfa_uncooked {
             ]
         );
 
+        let nfa = nfa_uncooked(lexer(b"a{2}{2}")).unwrap();
+        // 0 -a- 1 - 2 -a- 3 - 4 -a- 5 - 6 -a- 7
+        assert_eq!(nfa.nodes, 8);
+        assert_eq!(
+            HashSet::from_iter(nfa.eps_edges),
+            HashSet::from([(1, 2), (3, 4), (5, 6)])
+        );
+
         let nfa = nfa_uncooked(lexer(b"a{1,2}b{,1}")).unwrap();
         // 0 -a- 1 - 2 -a-,- 3 - 4 -b-,- 5
         assert_eq!(nfa.nodes, 6);
@@ -301,6 +447,7 @@ mod test_nfa_uncooked {
         //                                 < - - - - - - - - -
         assert_eq!(nfa.nodes, 20);
         assert_eq!(nfa.begin, 0);
+        assert_eq!(nfa.accept, HashMap::from([(19, 0)]));
         assert_eq!(
             HashSet::from_iter(nfa.eps_edges.clone()),
             HashSet::from([
@@ -342,8 +489,97 @@ mod test_nfa_uncooked {
         assert_eq!(nfa, nfa2);
     }
 
+    #[test]
+    fn rules() {
+        let nfa = nfa_uncooked_rules(vec![lexer(b"a"), lexer(b"\\Ab\\Z"), lexer(b"c")]).unwrap();
+        // 8 < 0 -a- 1, 4* - 2 -b- 3 - 5*, 6 -c- 7
+        assert_eq!(nfa.nodes, 9);
+        assert_eq!(nfa.begin, 8);
+        assert_eq!(nfa.groups, 1);
+        assert_eq!(nfa.accept, HashMap::from([(1, 0), (5, 1), (7, 2)]));
+        assert_eq!(
+            nfa.edges,
+            vec![
+                (0, 1, charset!(b'a')),
+                (2, 3, charset!(b'b')),
+                (6, 7, charset!(b'c'))
+            ]
+        );
+        assert_eq!(
+            HashSet::from_iter(nfa.eps_edges),
+            HashSet::from([(4, 2), (3, 5), (8, 0), (8, 4), (8, 6)])
+        );
+
+        let nfa = nfa_uncooked_rules(vec![]).unwrap();
+        assert_eq!(nfa.nodes, 1);
+        assert_eq!(nfa.accept, HashMap::new());
+
+        let err = nfa_uncooked_rules(vec![lexer(b"a"), lexer(b"b"), lexer(b"(")]).unwrap_err();
+        assert_eq!(err, (2, Error::Balance.at(1..1)));
+    }
+
+    #[test]
+    fn reverse() {
+        let nfa = nfa_uncooked(lexer(b"ab")).unwrap().reverse(true);
+        // 4 -*- 4 - 3 -b- 2 - 1 -a- 0
+        assert_eq!(nfa.nodes, 5);
+        assert_eq!(nfa.begin, 4);
+        assert_eq!(nfa.accept, HashMap::from([(0, 0)]));
+        assert_eq!(
+            nfa.edges,
+            vec![
+                (1, 0, charset!(b'a')),
+                (3, 2, charset!(b'b')),
+                (4, 4, Charset::ALL)
+            ]
+        );
+        assert_eq!(
+            HashSet::from_iter(nfa.eps_edges),
+            HashSet::from([(2, 1), (4, 3)])
+        );
+
+        let nfa = nfa_uncooked(lexer(b"\\A(a)\\Z")).unwrap().reverse(false);
+        assert_eq!(nfa.groups, 0);
+        assert_eq!(nfa.head, HashMap::new());
+        assert_eq!(nfa.tail, HashMap::new());
+        assert_eq!(nfa.accept, HashMap::from([(2, 0)]));
+        assert_eq!(nfa.edges, vec![(1, 0, charset!(b'a'))]);
+    }
+
+    #[test]
+    fn anchors() {
+        let nfa = nfa_uncooked(lexer(b"^a*$")).unwrap();
+        // 0 -^- 1 - 4 - 2 -a- 3 - 5 - 6 -$- 7
+        assert_eq!(nfa.nodes, 8);
+        assert_eq!(
+            nfa.asserts,
+            vec![(0, 1, Assert::TextStart), (6, 7, Assert::TextEnd)]
+        );
+        assert_eq!(
+            nfa.reverse(false).asserts,
+            vec![(1, 0, Assert::TextEnd), (7, 6, Assert::TextStart)]
+        );
+
+        let nfa = nfa_uncooked(lexer(b"^{2}")).unwrap();
+        assert_eq!(
+            nfa.asserts,
+            vec![(0, 1, Assert::TextStart), (2, 3, Assert::TextStart)]
+        );
+    }
+
+    #[test]
+    fn named_groups() {
+        let nfa = nfa_uncooked(lexer(b"(?<a>x)(y)\\A(?<b>z)\\Z")).unwrap();
+        assert_eq!(nfa.groups, 3);
+        assert_eq!(
+            nfa.names,
+            HashMap::from([(String::from("a"), 0), (String::from("b"), 2)])
+        );
+        assert_eq!(nfa_err(b"(?<a>x)(?<a>y)"), Error::Name);
+    }
+
     fn nfa_err(s: &[u8]) -> Error {
-        return nfa_uncooked(lexer(s)).unwrap_err();
+        return nfa_uncooked(lexer(s)).unwrap_err().error;
     }
 
     #[test]
@@ -356,5 +592,16 @@ mod test_nfa_uncooked {
         assert_eq!(nfa_err(b"a\\Z"), Error::Group);
         assert_eq!(nfa_err(b"a|*"), Error::Postfix);
         assert_eq!(nfa_err(b"\\Aa|\\Z"), Error::Postfix);
+        assert_eq!(nfa_err(b"a{255}{255}"), Error::TooLarge);
+        assert_eq!(nfa_err(b"(a{100}|b{100}){30}"), Error::TooLarge);
+        let config = Config {
+            max_nfa_nodes: 9,
+            ..Config::default()
+        };
+        assert!(nfa_uncooked(Lexer::new(b"a{4}", config.clone())).is_ok());
+        assert_eq!(
+            nfa_uncooked(Lexer::new(b"a{5}", config)),
+            Err(Error::TooLarge.at(1..4))
+        );
     }
 }
@@ -0,0 +1,235 @@
+/*
+ * Regex builder
+ *
+ * - combinators build Ast directly from bytes and charsets, no syntax is parsed or escaped
+ * - groups, made by combinators, are numbered in order of their start after the groups already in the tree,
+ *   so a tree, built only by combinators, is numbered as if it was parsed from its Display, and a parsed tree
+ *   keeps the numbers of its groups
+ * - nfa_from_ast checks the tree (empty concatenations and alternations, bad repeat bounds, duplicate names
+ *   and group indices, nfa size) and builds the same nfa as nfa_uncooked does for the pattern
+ */
+
+/// sequence of bytes
+pub fn lit(s: &[u8]) -> Ast {
+    concat(s.iter().map(|c| Ast::Char(charset!(*c))))
+}
+
+/// any byte of the charset
+pub fn class(c: Charset) -> Ast {
+    Ast::Char(c)
+}
+
+pub fn concat(items: impl IntoIterator<Item = Ast>) -> Ast {
+    Ast::concat(items.into_iter().collect())
+}
+
+pub fn alt(items: impl IntoIterator<Item = Ast>) -> Ast {
+    Ast::alt(items.into_iter().collect())
+}
+
+/// index of a group, made by a combinator, till the tree is numbered
+pub const UNNUMBERED: usize = usize::MAX;
+
+/// capture group
+pub fn group(t: Ast) -> Ast {
+    Ast::Group(UNNUMBERED, None, Box::new(t))
+}
+
+/// named capture group
+pub fn named(name: &str, t: Ast) -> Ast {
+    Ast::Group(UNNUMBERED, Some(String::from(name)), Box::new(t))
+}
+
+impl Ast {
+    /// from `min` to `max` iterations of the item, None is unbounded
+    pub fn repeat(self, min: u32, max: Option<u32>) -> Ast {
+        Ast::Repeat(Box::new(self), min, max)
+    }
+
+    /// same tree with groups, made by combinators, numbered in order of their start after other groups
+    pub fn numbered(&self) -> Ast {
+        self.number(&mut self.max_group().map_or(0, |g| g + 1))
+    }
+
+    /// max index of a numbered group
+    fn max_group(&self) -> Option<usize> {
+        match self {
+            Ast::Group(g, _, t) if *g != UNNUMBERED => Some(*g).max(t.max_group()),
+            Ast::Group(_, _, t) | Ast::Repeat(t, _, _) => t.max_group(),
+            Ast::Concat(v) | Ast::Alt(v) => v.iter().filter_map(|t| t.max_group()).max(),
+            Ast::Char(_) | Ast::Unicode(_) | Ast::Assert(_) => None,
+        }
+    }
+
+    fn number(&self, next: &mut usize) -> Ast {
+        match self {
+            Ast::Group(UNNUMBERED, name, t) => {
+                let g = *next;
+                *next += 1;
+                Ast::Group(g, name.clone(), Box::new(t.number(next)))
+            }
+            Ast::Group(g, name, t) => Ast::Group(*g, name.clone(), Box::new(t.number(next))),
+            Ast::Concat(v) => Ast::Concat(v.iter().map(|t| t.number(next)).collect()),
+            Ast::Alt(v) => Ast::Alt(v.iter().map(|t| t.number(next)).collect()),
+            Ast::Repeat(t, min, max) => Ast::Repeat(Box::new(t.number(next)), *min, *max),
+            t => t.clone(),
+        }
+    }
+
+    /// check the tree, collect group names and indices
+    fn check(&self, names: &mut HashMap<String, usize>, groups: &mut HashSet<usize>) -> Result<()> {
+        match self {
+            Ast::Char(_) | Ast::Unicode(_) | Ast::Assert(_) => Ok(()),
+            Ast::Concat(v) | Ast::Alt(v) if v.is_empty() => Err(Error::Empty),
+            Ast::Concat(v) | Ast::Alt(v) => v.iter().try_for_each(|t| t.check(names, groups)),
+            Ast::Repeat(_, min, max) if max.is_some_and(|m| m == 0 || m < *min) => {
+                Err(Error::Repeat)
+            }
+            Ast::Repeat(t, _, _) => t.check(names, groups),
+            Ast::Group(g, name, t) => {
+                if let Some(name) = name {
+                    if names.insert(name.clone(), *g).is_some() {
+                        return Err(Error::Name);
+                    }
+                }
+                if !groups.insert(*g) {
+                    return Err(Error::Name);
+                }
+                t.check(names, groups)
+            }
+        }
+    }
+}
+
+/// nfa of a tree, built by hand or by build_ast, groups made by combinators are numbered (see Ast::numbered)
+pub fn nfa_from_ast(ast: &Ast, config: &Config) -> Result<NFAUncooked> {
+    let ast = &ast.numbered();
+    let mut nfa = NFAUncooked::new();
+    let mut groups = HashSet::new();
+    ast.check(&mut nfa.names, &mut groups)?;
+    nfa.groups = groups.iter().max().map_or(0, |g| g + 1);
+    if ast.size() > config.max_nfa_nodes {
+        return Err(Error::TooLarge);
+    }
+    if ast.has_word_asserts() && !config.esc_charset.contains_key(&b'w') {
+        return Err(Error::Escape);
+    }
+    let (begin, end, _) = nfa.build(ast, config);
+    nfa.begin = begin;
+    nfa.accept.insert(end, 0);
+    Ok(nfa)
+}
+
+impl Ast {
+    fn has_word_asserts(&self) -> bool {
+        match self {
+            Ast::Assert(x) => x.word(),
+            Ast::Char(_) | Ast::Unicode(_) => false,
+            Ast::Concat(v) | Ast::Alt(v) => v.iter().any(|t| t.has_word_asserts()),
+            Ast::Repeat(t, _, _) | Ast::Group(_, _, t) => t.has_word_asserts(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_builder {
+    use super::*;
+
+    fn same_nfa(ast: Ast, s: &[u8]) {
+        let config = Config::default();
+        let nfa = nfa_uncooked(Lexer::new(s, config.clone())).unwrap();
+        assert_eq!(nfa_from_ast(&ast, &config).unwrap(), nfa);
+        assert_eq!(ast.numbered().to_string().as_bytes(), s);
+    }
+
+    #[test]
+    fn same_as_pattern() {
+        same_nfa(lit(b"a+b"), b"a\\+b");
+        same_nfa(
+            concat([
+                alt([lit(b"ab"), class(charset!([b'0', b'9']))]).repeat(0, None),
+                lit(b"."),
+            ]),
+            b"(ab|[0-9])*\\.",
+        );
+        same_nfa(
+            concat([
+                group(group(lit(b"a")).repeat(1, Some(3))),
+                named("x", lit(b"b")),
+            ]),
+            b"\\A(\\A(a)\\Z{1,3})\\Z(?<x>b)",
+        );
+        same_nfa(
+            concat([Ast::Assert(Assert::TextStart), lit(b"x").repeat(2, None)]),
+            b"^x{2,}",
+        );
+
+        // parsed trees keep their group numbers: \A opens group 0 (around b) before (a) opens group 1
+        let config = Config {
+            auto_groups: true,
+            ..Config::default()
+        };
+        let s = b"\\A(a)b\\Z";
+        let ast = build_ast(Lexer::new(s, config.clone())).unwrap();
+        let nfa = nfa_uncooked(Lexer::new(s, config.clone())).unwrap();
+        assert_eq!(nfa_from_ast(&ast, &config).unwrap(), nfa);
+        let r = compile_ast(&ast, config.clone()).unwrap();
+        assert_eq!(
+            r.captures(b"ab"),
+            Some(vec![(Some(1), Some(2)), (Some(0), Some(1))])
+        );
+        assert_eq!(
+            r.captures(b"ab"),
+            super::compile(s, config).unwrap().captures(b"ab")
+        );
+        let ast = concat([ast, group(lit(b"c"))]).numbered();
+        assert!(matches!(&ast, Ast::Concat(v) if matches!(v[1], Ast::Group(2, _, _))));
+    }
+
+    #[test]
+    fn compile() {
+        let ast = concat([
+            named("key", class(charset!([b'a', b'z'])).repeat(1, None)),
+            lit(b"="),
+            group(lit(b"1")),
+        ]);
+        let r = compile_ast(&ast, Config::default()).unwrap();
+        assert_eq!(r.groups(), 2);
+        assert_eq!(r.capture(b"ab=1", "key"), Some((Some(0), Some(2))));
+        assert_eq!(
+            r.captures(b"ab=1"),
+            Some(vec![(Some(0), Some(2)), (Some(3), Some(4))])
+        );
+        assert_eq!(r.posix_captures(b"ab=1"), r.captures(b"ab=1"));
+        assert_eq!(r.find(b"x ab=1"), Some((2, 6)));
+    }
+
+    #[test]
+    fn errors() {
+        let err = |ast: Ast| compile_ast(&ast, Config::default()).err();
+        assert_eq!(err(concat([])), Some(Error::Empty));
+        assert_eq!(err(lit(b"a").repeat(2, Some(1))), Some(Error::Repeat));
+        assert_eq!(err(lit(b"a").repeat(0, Some(0))), Some(Error::Repeat));
+        assert_eq!(
+            err(concat([named("a", lit(b"x")), named("a", lit(b"y"))])),
+            Some(Error::Name)
+        );
+        let dup = |t: Ast| Ast::Group(0, None, Box::new(t));
+        assert_eq!(
+            err(concat([dup(lit(b"a")), dup(lit(b"b"))])),
+            Some(Error::Name)
+        );
+        assert_eq!(
+            err(lit(b"a").repeat(1000, Some(1000)).repeat(1000, Some(1000))),
+            Some(Error::TooLarge)
+        );
+        assert_eq!(
+            err(class(charset!(b'a')).repeat(u32::MAX, None)),
+            Some(Error::TooLarge)
+        );
+        assert_eq!(
+            err(concat(vec![lit(b"a").repeat(u32::MAX, Some(u32::MAX)); 3])),
+            Some(Error::TooLarge)
+        );
+    }
+}
@@ -2,22 +2,29 @@
  * Regex Engine for rcclex
  */
 
+/// bitmap for all 256 bytes: c[0] holds 0..=127, c[1] holds 128..=255
 #[derive(Debug, PartialEq, Clone)]
 pub struct Charset {
-    c: u128,
+    c: [u128; 2],
 }
 
 pub struct CharsetIter {
-    c: u128,
-    fwd: u8,
-    bwd: u8,
+    c: [u128; 2],
+    fwd: u16,
+    bwd: u16,
 }
 
 impl Charset {
-    pub const ALL: Charset = Charset { c: std::u128::MAX };
+    pub const ALL: Charset = Charset {
+        c: [u128::MAX, u128::MAX],
+    };
 
     pub fn new() -> Charset {
-        Charset { c: 0 }
+        Charset { c: [0, 0] }
+    }
+
+    fn bit(c: u8) -> (usize, u128) {
+        ((c >> 7) as usize, 1u128 << (c & 127))
     }
 
     pub fn iter(&self) -> CharsetIter {
@@ -29,17 +36,24 @@ impl Charset {
     }
 
     pub fn inv(mut self) -> Charset {
-        self.c ^= std::u128::MAX;
+        self.c[0] ^= u128::MAX;
+        self.c[1] ^= u128::MAX;
         return self;
     }
 
     pub fn add(&mut self, s: &Charset) {
-        self.c |= s.c;
+        self.c[0] |= s.c[0];
+        self.c[1] |= s.c[1];
     }
 
     pub fn add_char(&mut self, c: u8) {
-        assert!(c <= 127);
-        self.c |= 1u128 << c;
+        let (i, b) = Self::bit(c);
+        self.c[i] |= b;
+    }
+
+    pub fn remove_char(&mut self, c: u8) {
+        let (i, b) = Self::bit(c);
+        self.c[i] &= !b;
     }
 
     pub fn add_range(&mut self, a: u8, b: u8) {
@@ -49,8 +63,19 @@ impl Charset {
         }
     }
 
+    pub fn contains(&self, c: u8) -> bool {
+        let (i, b) = Self::bit(c);
+        (self.c[i] & b) != 0
+    }
+
     pub fn empty(&self) -> bool {
-        self.c == 0
+        self.c == [0, 0]
+    }
+}
+
+impl Default for Charset {
+    fn default() -> Charset {
+        Charset::new()
     }
 }
 
@@ -70,13 +95,20 @@ impl std::fmt::Display for Charset {
     }
 }
 
+impl CharsetIter {
+    fn contains(&self, c: u16) -> bool {
+        let (i, b) = Charset::bit(c as u8);
+        (self.c[i] & b) != 0
+    }
+}
+
 impl Iterator for CharsetIter {
     type Item = u8;
     fn next(&mut self) -> Option<u8> {
-        while self.fwd <= 127 {
+        while self.fwd <= 255 {
             self.fwd += 1;
-            if (self.c & 1u128 << (self.fwd - 1)) != 0 {
-                return Some(self.fwd - 1);
+            if self.contains(self.fwd - 1) {
+                return Some((self.fwd - 1) as u8);
             }
         }
         None
@@ -85,10 +117,10 @@ impl Iterator for CharsetIter {
 
 impl DoubleEndedIterator for CharsetIter {
     fn next_back(&mut self) -> Option<u8> {
-        while self.bwd <= 127 {
+        while self.bwd <= 255 {
             self.bwd += 1;
-            if (self.c & 1u128 << (128 - self.bwd)) != 0 {
-                return Some(128 - self.bwd);
+            if self.contains(256 - self.bwd) {
+                return Some((256 - self.bwd) as u8);
             }
         }
         None
@@ -126,32 +158,40 @@ macro_rules! charset {
 mod test_charset {
     #[test]
     fn construct() {
-        assert_eq!(charset!(0).c, 1);
-        assert_eq!(charset!(0, 2).c, 5);
-        assert_eq!(charset!(0, 1, 2).c, 7);
-        assert_eq!(charset!([0, 2]; 5).c, 7 | 32);
-        assert_eq!(charset!([0, 127]).c, std::u128::MAX);
+        assert_eq!(charset!(0).c, [1, 0]);
+        assert_eq!(charset!(0, 2).c, [5, 0]);
+        assert_eq!(charset!(0, 1, 2).c, [7, 0]);
+        assert_eq!(charset!([0, 2]; 5).c, [7 | 32, 0]);
+        assert_eq!(charset!([0, 127]).c, [u128::MAX, 0]);
+        assert_eq!(charset!(128, 255).c, [0, 1 | 1 << 127]);
+        assert_eq!(charset!([0, 255]), super::Charset::ALL);
     }
 
     #[test]
     fn methods() {
         let mut s = charset!([1, 2]);
-        assert_eq!(s.c, 6);
-        s.add(&charset!(5));
-        assert_eq!(s.c, 38);
-        assert_eq!(s.inv().c, std::u128::MAX - 38);
+        assert_eq!(s.c, [6, 0]);
+        s.add(&charset!(5, 130));
+        assert_eq!(s.c, [38, 4]);
+        assert!(s.contains(130));
+        assert!(!s.contains(131));
+        s.remove_char(130);
+        s.remove_char(131);
+        assert_eq!(s.c, [38, 0]);
+        s.add_char(130);
+        assert_eq!(s.inv().c, [u128::MAX - 38, u128::MAX - 4]);
     }
 
     #[test]
     fn iter() {
-        let s = charset!([0, 127]);
+        let s = charset!([0, 255]);
         let mut it = s.iter();
-        for i in 0..=127 {
+        for i in 0..=255 {
             assert_eq!(it.next(), Some(i));
         }
         assert_eq!(it.next(), None);
 
-        let s = charset!([8, 27], [50, 52]; 6);
+        let s = charset!([8, 27], [50, 52]; 6, 200);
         let mut it = s.iter();
         assert_eq!(it.next(), Some(6));
         for i in 8..=27 {
@@ -160,6 +200,9 @@ mod test_charset {
         for i in 50..=52 {
             assert_eq!(it.next(), Some(i));
         }
+        assert_eq!(it.next(), Some(200));
         assert_eq!(it.next(), None);
+        assert_eq!(s.iter().next_back(), Some(200));
+        assert_eq!(charset!(0).iter().next_back(), Some(0));
     }
 }
@@ -0,0 +1,118 @@
+/*
+ * Byte equivalence classes
+ *
+ * - bytes are equal, if every charset of the nfa contains either both or none of them
+ * - classes are numbered in order of their first byte
+ * - look of a class is the look of its first byte (newline and word chars get their own classes, if assertions are used)
+ */
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ByteClasses {
+    /// class of each byte
+    pub map: [u8; 256],
+    pub count: usize,
+    /// look of each class
+    pub look: Vec<usize>,
+}
+
+impl ByteClasses {
+    /// `word` are word chars for looks, if word assertions are used
+    pub fn new<'a>(sets: impl Iterator<Item = &'a Charset>, word: Option<&Charset>) -> ByteClasses {
+        let mut unique: Vec<&Charset> = Vec::new();
+        for s in sets {
+            if !unique.contains(&s) {
+                unique.push(s);
+            }
+        }
+        let mut ids: HashMap<Vec<bool>, u8> = HashMap::new();
+        let mut map = [0; 256];
+        for (c, class) in map.iter_mut().enumerate() {
+            let key: Vec<bool> = unique.iter().map(|s| s.contains(c as u8)).collect();
+            let id = ids.len() as u8;
+            *class = *ids.entry(key).or_insert(id);
+        }
+        let mut classes = ByteClasses {
+            map,
+            count: ids.len(),
+            look: vec![],
+        };
+        classes.look = classes
+            .reps()
+            .into_iter()
+            .map(|c| match c {
+                b'\n' => LOOK_NEWLINE,
+                _ if word.is_some_and(|w| w.contains(c)) => LOOK_WORD,
+                _ => LOOK_OTHER,
+            })
+            .collect();
+        classes
+    }
+
+    pub fn get(&self, c: u8) -> usize {
+        self.map[c as usize] as usize
+    }
+
+    /// look of the byte `c`, None is the edge of the input
+    pub fn look(&self, c: Option<u8>) -> usize {
+        match c {
+            None => LOOK_EDGE,
+            Some(c) => self.look[self.get(c)],
+        }
+    }
+
+    /// first byte of each class
+    pub fn reps(&self) -> Vec<u8> {
+        let mut reps: Vec<u8> = Vec::new();
+        for c in 0..=255 {
+            if self.get(c) == reps.len() {
+                reps.push(c);
+            }
+        }
+        reps
+    }
+
+    pub fn bytes(&self, class: usize) -> impl Iterator<Item = u8> + '_ {
+        (0..=255).filter(move |c| self.get(*c) == class)
+    }
+}
+
+#[cfg(test)]
+mod test_classes {
+    use super::*;
+
+    #[test]
+    fn classes() {
+        let c = ByteClasses::new(
+            [
+                charset!([b'a', b'z']),
+                charset!(b'x'),
+                charset!([b'a', b'z']),
+            ]
+            .iter(),
+            None,
+        );
+        assert_eq!(c.count, 3);
+        assert_eq!(c.get(0), 0);
+        assert_eq!(c.get(b'a'), 1);
+        assert_eq!(c.get(b'z'), 1);
+        assert_eq!(c.get(b'x'), 2);
+        assert_eq!(c.get(b'{'), 0);
+        assert_eq!(c.get(200), 0);
+        assert_eq!(c.reps(), vec![0, b'a', b'x']);
+        assert_eq!(c.bytes(2).collect::<Vec<_>>(), vec![b'x']);
+        assert_eq!(c.look(None), LOOK_EDGE);
+        assert_eq!(c.look(Some(b'\n')), LOOK_OTHER);
+
+        let c = ByteClasses::new([charset!(b'\n'), charset!([b'a', b'z'])].iter(), None);
+        assert_eq!(c.look, vec![LOOK_OTHER, LOOK_NEWLINE, LOOK_OTHER]);
+        assert_eq!(c.look(Some(b'\n')), LOOK_NEWLINE);
+
+        let w = charset!([b'a', b'z']);
+        let c = ByteClasses::new([charset!(b'\n'), w.clone()].iter(), Some(&w));
+        assert_eq!(c.look, vec![LOOK_OTHER, LOOK_NEWLINE, LOOK_WORD]);
+
+        let c = ByteClasses::new([].iter(), None);
+        assert_eq!(c.count, 1);
+        assert_eq!(c.reps(), vec![0]);
+    }
+}
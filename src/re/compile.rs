@@ -1,32 +1,333 @@
 pub mod re {
     use std::collections::HashSet;
+    use std::sync::OnceLock;
 
     pub trait Automation {
-        fn nodes(&self) -> impl Iterator<Item = (HashSet<usize>, HashSet<usize>)>;
+        /// (group heads, group tails, accept) for each node
+        fn nodes(&self) -> impl Iterator<Item = (HashSet<usize>, HashSet<usize>, bool)>;
         fn edges(&self) -> impl Iterator<Item = (usize, usize, Option<u8>)>;
     }
 
     pub struct Regex {
-        pub nodes: Vec<HashMap<u8, usize>>,
+        pub classes: ByteClasses,
+        /// nodes[n][class] is the next state or DEAD
+        pub nodes: Vec<Vec<usize>>,
+        /// start state for each look behind
+        pub start: Vec<usize>,
         pub head: Vec<HashSet<usize>>,
         pub tail: Vec<HashSet<usize>>,
+        /// accept[n][look] is true, if state n accepts before a byte with such look ahead
+        pub accept: Vec<Vec<bool>>,
+        /// unanchored dfa for reversed input, accepts where a match starts; built on the first search
+        reverse: OnceLock<Reverse>,
+        /// tagged dfa to extract groups, built on the first captures
+        tagged: OnceLock<Tagged>,
+        /// pattern tree to extract groups by posix rules, built on the first posix captures
+        posix: OnceLock<Posix>,
+        /// group index for each named group
+        pub names: HashMap<String, usize>,
+        /// nfa simulation, if dfas are not built: then they have no states
+        pub pike: Option<PikeVM>,
+        /// lazy dfa, used for matching instead of pike vm, if present
+        pub lazy: Option<LazyDFA>,
+        /// nfa and tree, that structures above are built from on demand
+        nfa: NFAUncooked,
+        ast: Ast,
+        /// max number of states of dfas, built on demand
+        limit: usize,
+        lazy_cache: usize,
+        /// search resumes after an empty match at the next char, not byte (see Config::utf8)
+        utf8: bool,
     }
 
-    pub fn compile(s: &[u8], config: Config) -> Result<Regex> {
+    /// reversed dfa, or lazy reversed dfa, if the dfa gets too large or the regex has no dfa
+    enum Reverse {
+        Dfa(DFA),
+        Lazy(LazyDFA),
+    }
+
+    /// tagged dfa, or pike vm, if the dfa gets too large
+    enum Tagged {
+        Dfa(TDFA),
+        Pike(PikeVM),
+    }
+
+    /// compile a pattern, syntax errors carry the span of the pattern, where they are found
+    pub fn compile(s: &[u8], config: Config) -> std::result::Result<Regex, Diagnostic> {
+        if !config.is_valid() {
+            return Err(Error::Config.into());
+        }
+        let (nfa, ast) = match config.simplify {
+            // the parser counts nodes, as nfa_uncooked does, errors of the simplified tree span the pattern
+            true => {
+                let ast = build_ast(Lexer::new(s, config.clone()))?
+                    .simplify()
+                    .numbered();
+                let nfa = nfa_from_ast(&ast, &config).map_err(|e| e.at(0..s.len()))?;
+                (nfa, ast)
+            }
+            false => nfa_uncooked_ast(Lexer::new(s, config.clone()))?,
+        };
+        Ok(compile_nfa(nfa, ast, &config)?)
+    }
+
+    /// compile a tree, built by hand or by build_ast, groups are numbered in order of their start
+    /// (the tree is simplified first with Config::simplify)
+    pub fn compile_ast(ast: &Ast, config: Config) -> Result<Regex> {
         if !config.is_valid() {
             return Err(Error::Config);
         }
-        let dfa = build_dfa(build_nfa(Lexer::new(s, config))?);
+        let ast = match config.simplify {
+            true => ast.simplify().numbered(),
+            false => ast.numbered(),
+        };
+        let nfa = nfa_from_ast(&ast, &config)?;
+        compile_nfa(nfa, ast, &config)
+    }
+
+    /// forward dfa and the structures for the engine; with Engine::Auto, reversed and tagged dfas are built
+    /// on demand, Engine::DFA builds all of them or fails
+    fn compile_nfa(nfa: NFAUncooked, ast: Ast, config: &Config) -> Result<Regex> {
+        let engine = config.engine;
+        check_memory(&nfa, config)?;
+        let max = max_dfa_states(&nfa, config);
+        let limit = config.dfa_states.min(max);
+        let (dfa, reverse, tagged) = match engine {
+            Engine::Auto => (
+                build_dfa_limited(NFA::cook(nfa.clone()), limit).map(|dfa| dfa.minimize()),
+                OnceLock::new(),
+                OnceLock::new(),
+            ),
+            Engine::DFA => {
+                let (dfa, reverse, tagged) = build_dfas(&nfa, max).ok_or(Error::TooLarge)?;
+                (
+                    Some(dfa),
+                    OnceLock::from(Reverse::Dfa(reverse)),
+                    OnceLock::from(Tagged::Dfa(tagged)),
+                )
+            }
+            Engine::PikeVM | Engine::LazyDFA => (None, OnceLock::new(), OnceLock::new()),
+        };
+        let lazy = match (engine, &dfa) {
+            (Engine::PikeVM, _) | (_, Some(_)) => None,
+            _ => Some(build_lazy_dfa(NFA::cook(nfa.clone()), config.lazy_cache)),
+        };
+        let pike = dfa.is_none().then(|| build_pike_vm(&nfa));
+        let dfa = dfa.unwrap_or_else(|| DFA::empty(nfa.classes()));
         Ok(Regex {
+            classes: dfa.classes,
             nodes: dfa.nodes,
+            start: dfa.start,
             head: dfa.head,
             tail: dfa.tail,
+            accept: dfa
+                .accept
+                .iter()
+                .map(|a| a.iter().map(|s| !s.is_empty()).collect())
+                .collect(),
+            reverse,
+            tagged,
+            posix: OnceLock::new(),
+            names: nfa.names.clone(),
+            pike,
+            lazy,
+            nfa,
+            ast,
+            limit,
+            lazy_cache: config.lazy_cache,
+            utf8: config.utf8,
         })
     }
 
+    /// dfa, reversed dfa and tagged dfa, unless any of them gets more than `limit` states
+    fn build_dfas(nfa: &NFAUncooked, limit: usize) -> Option<(DFA, DFA, TDFA)> {
+        let reverse = build_dfa_limited(NFA::cook(nfa.reverse(true)), limit)?.minimize();
+        let tagged = build_tdfa_limited(nfa, limit)?;
+        let dfa = build_dfa_limited(NFA::cook(nfa.clone()), limit)?.minimize();
+        Some((dfa, reverse, tagged))
+    }
+
+    impl Regex {
+        fn reverse(&self) -> &Reverse {
+            self.reverse.get_or_init(|| {
+                let reverse = || NFA::cook(self.nfa.reverse(true));
+                let dfa = match self.lazy {
+                    Some(_) => None,
+                    None => build_dfa_limited(reverse(), self.limit),
+                };
+                match dfa {
+                    Some(dfa) => Reverse::Dfa(dfa.minimize()),
+                    None => Reverse::Lazy(build_lazy_dfa(reverse(), self.lazy_cache)),
+                }
+            })
+        }
+
+        fn tagged(&self) -> &Tagged {
+            self.tagged
+                .get_or_init(|| match build_tdfa_limited(&self.nfa, self.limit) {
+                    Some(tagged) => Tagged::Dfa(tagged),
+                    None => Tagged::Pike(build_pike_vm(&self.nfa)),
+                })
+        }
+
+        fn posix(&self) -> &Posix {
+            self.posix
+                .get_or_init(|| build_posix(&self.ast, self.nfa.groups))
+        }
+    }
+
+    /// fail, if cooking the nfa (or its reversed copy) takes more memory, than allowed
+    fn check_memory(nfa: &NFAUncooked, config: &Config) -> Result<()> {
+        let nodes = nfa.nodes + 1;
+        match nodes
+            .saturating_mul(nodes)
+            .saturating_mul(size_of::<Charset>())
+            > config.max_memory
+        {
+            true => Err(Error::TooLarge),
+            false => Ok(()),
+        }
+    }
+
+    /// max number of dfa states, that fit into memory limit
+    fn max_dfa_states(nfa: &NFAUncooked, config: &Config) -> usize {
+        let state = (2 * nfa.classes().count + 2 * LOOKS) * size_of::<usize>();
+        config.max_dfa_states.min(config.max_memory / state)
+    }
+
+    /// compile several patterns into a single dfa, on failure return id of the failed rule
+    /// (none, if config is invalid or rules together exceed the limits)
+    pub fn compile_rules(
+        rules: &[&[u8]],
+        config: Config,
+    ) -> std::result::Result<DFA, (Option<usize>, Diagnostic)> {
+        let nfa = rules_nfa(rules, &config)?;
+        let limit = max_dfa_states(&nfa, &config);
+        let dfa = build_dfa_limited(NFA::cook(nfa), limit).ok_or((None, Error::TooLarge.into()))?;
+        Ok(dfa.minimize())
+    }
+
+    /// compile several patterns into a single lazy dfa, on failure return id of the failed rule as compile_rules
+    pub fn compile_rules_lazy(
+        rules: &[&[u8]],
+        config: Config,
+    ) -> std::result::Result<LazyDFA, (Option<usize>, Diagnostic)> {
+        let nfa = rules_nfa(rules, &config)?;
+        Ok(build_lazy_dfa(NFA::cook(nfa), config.lazy_cache))
+    }
+
+    /// nfa of all rules, within the limits
+    fn rules_nfa(
+        rules: &[&[u8]],
+        config: &Config,
+    ) -> std::result::Result<NFAUncooked, (Option<usize>, Diagnostic)> {
+        if !config.is_valid() {
+            return Err((None, Error::Config.into()));
+        }
+        let lexers = rules.iter().map(|s| Lexer::new(s, config.clone()));
+        let nfa = nfa_uncooked_rules(lexers.collect()).map_err(|(r, e)| match e.error {
+            // earlier rules may take the room, the rule is blamed only if it is too large alone
+            Error::TooLarge if nfa_uncooked(Lexer::new(rules[r], config.clone())).is_ok() => {
+                (None, Error::TooLarge.into())
+            }
+            _ => (Some(r), e),
+        })?;
+        check_memory(&nfa, config).map_err(|e| (None, e.into()))?;
+        Ok(nfa)
+    }
+
     include!("charset.rs");
+    include!("classes.rs");
+    include!("utf8.rs");
     include!("config.rs");
+    include!("diagnostic.rs");
+    include!("assert.rs");
     include!("lexer.rs");
+    include!("ast.rs");
+    include!("builder.rs");
+    include!("simplify.rs");
     include!("build_nfa.rs");
     include!("build_dfa.rs");
+    include!("minimize.rs");
+    include!("tdfa.rs");
+    include!("posix.rs");
+    include!("pike.rs");
+    include!("lazy.rs");
+    include!("matching.rs");
+    include!("search.rs");
+
+    #[cfg(test)]
+    mod test_compile {
+        use super::*;
+
+        #[test]
+        fn limits() {
+            let re = b"(a|b)*a(a|b){8}";
+            let config = |engine, max_dfa_states, max_memory| Config {
+                engine,
+                max_dfa_states,
+                max_memory,
+                ..Config::default()
+            };
+            assert_eq!(
+                compile(re, config(Engine::DFA, 100, 1 << 30))
+                    .err()
+                    .map(|e| e.error),
+                Some(Error::TooLarge)
+            );
+            assert_eq!(
+                compile(re, config(Engine::DFA, 1000, 1 << 16))
+                    .err()
+                    .map(|e| e.error),
+                Some(Error::TooLarge)
+            );
+            assert!(compile(re, config(Engine::DFA, 1000, 1 << 30)).is_ok());
+            let auto = compile(re, config(Engine::Auto, 100, 1 << 30)).unwrap();
+            assert!(auto.lazy.is_some());
+            assert_eq!(
+                compile(b"a{40}", config(Engine::Auto, 100, 1 << 12))
+                    .err()
+                    .map(|e| e.error),
+                Some(Error::TooLarge)
+            );
+            assert_eq!(
+                compile_rules(&[b"a", re], config(Engine::Auto, 100, 1 << 30)).err(),
+                Some((None, Error::TooLarge.into()))
+            );
+
+            // any nfa within the default node limit fits into the default memory limit
+            let config = Config::default();
+            let nfa = nfa_uncooked(Lexer::new(b"a{250}{10}", config.clone())).unwrap();
+            assert_eq!(nfa.nodes, config.max_nfa_nodes);
+            assert!(check_memory(&nfa, &config).is_ok());
+        }
+
+        #[test]
+        fn on_demand() {
+            let config = Config {
+                auto_groups: true,
+                ..Config::default()
+            };
+            let r = compile(b"(a+)b", config).unwrap();
+            assert!(r.is_match(b"aab"));
+            assert!(
+                r.reverse.get().is_none() && r.tagged.get().is_none() && r.posix.get().is_none()
+            );
+            assert_eq!(r.find(b"xaab"), Some((1, 4)));
+            assert!(r.reverse.get().is_some() && r.tagged.get().is_none());
+            assert_eq!(r.captures(b"aab"), Some(vec![(Some(0), Some(2))]));
+            assert_eq!(r.posix_captures(b"aab"), Some(vec![(Some(0), Some(2))]));
+            assert!(r.tagged.get().is_some() && r.posix.get().is_some());
+
+            // forward dfa is small, reversed unanchored one is not
+            let config = Config {
+                dfa_states: 100,
+                ..Config::default()
+            };
+            let r = compile(b"(a|b){8}a", config).unwrap();
+            assert!(r.lazy.is_none() && !r.nodes.is_empty());
+            assert_eq!(r.find(b"ccabababababc"), Some((2, 11)));
+            assert!(matches!(r.reverse.get(), Some(Reverse::Lazy(_))));
+        }
+    }
 }
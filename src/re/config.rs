@@ -1,11 +1,48 @@
 /// configuration for regex compilation
+#[derive(Clone)]
 pub struct Config {
     /// charset for '.'
     pub dot_charset: Charset,
-    /// charset for \a-\z \A-\Z (except A, Z, x, X)
+    /// charset for \a-\z \A-\Z (except A, Z, b, B, x, X), \w also defines word chars for \b and \B
     pub esc_charset: HashMap<u8, Charset>,
     /// prepend '(' with '\A', append ')' with '\Z'
     pub auto_groups: bool,
+    /// pattern and input are utf-8: chars, '.' and charsets match unicode chars, '\u{...}' is available
+    pub utf8: bool,
+    /// '^' and '$' match at line breaks, not only at the edges of the input
+    pub multiline: bool,
+    /// letters match in both cases, same as '(?i)' at the beginning of the pattern
+    pub case_insensitive: bool,
+    /// whitespace and '#' comments are ignored outside of charsets, same as '(?x)' at the beginning of the pattern
+    pub extended: bool,
+    /// simplify the pattern tree before nfa is built (see Ast::simplify)
+    pub simplify: bool,
+    /// engine to match with
+    pub engine: Engine,
+    /// with Engine::Auto, lazy dfa is used, if any of dfas gets more states
+    pub dfa_states: usize,
+    /// max size of lazy dfa cache in bytes, it is flushed when full
+    pub lazy_cache: usize,
+    /// compilation fails with Error::TooLarge, if nfa gets more nodes
+    pub max_nfa_nodes: usize,
+    /// compilation fails with Error::TooLarge, if a dfa, that must be built, gets more states
+    pub max_dfa_states: usize,
+    /// approximate limit of memory in bytes for cooking nfa and for tables of dfas and tagged dfa, they are limited
+    /// to fit into it; lazy dfa cache is limited by `lazy_cache`, pike vm and posix captures are not limited
+    pub max_memory: usize,
+}
+
+/// matching engine of compiled regex
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Engine {
+    /// dfa, unless it exceeds `Config::dfa_states`, lazy dfa otherwise
+    Auto,
+    /// dfa of any size
+    DFA,
+    /// nfa simulation, no dfa is built
+    PikeVM,
+    /// dfa states are built while matching, groups are extracted by pike vm
+    LazyDFA,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -23,6 +60,10 @@ pub enum Error {
     Union,
     Empty,
     Postfix,
+    Utf8,
+    Flag,
+    Name,
+    TooLarge,
 }
 
 impl std::fmt::Display for Error {
@@ -38,16 +79,23 @@ impl std::fmt::Display for Error {
             Self::Union => write!(f, "invalid usage of '|' or bad () balance"),
             Self::Empty => write!(f, "empty expression or sub-expression"),
             Self::Postfix => write!(f, "invalid usage of postfix operator"),
+            Self::Utf8 => write!(f, "invalid utf-8 sequence or code point"),
+            Self::Flag => write!(f, "unknown inline flag"),
+            Self::Name => write!(f, "invalid or duplicate group name"),
+            Self::TooLarge => write!(f, "pattern exceeds compilation limits"),
         }
     }
 }
 
-impl Config {
+impl Default for Config {
     /// create default configuration
     /// auto-goups are disabled
     /// '.' matches on any character
+    /// dfa is used, unless it gets more than 10000 states, lazy dfa cache is 1 MiB
+    /// compilation is limited to 5000 nfa nodes, 1000000 dfa states and 1 GiB of memory
+    /// (cooking nfa takes 32 bytes per pair of nodes, so any nfa within the node limit fits)
     /// default perl-regex charsets are defined (\t \n \s \S \d \D \w \W)
-    pub fn default() -> Config {
+    fn default() -> Config {
         let w = charset!([b'A', b'Z'], [b'a', b'z'], [b'0', b'9']; b'_');
         Config {
             dot_charset: Charset::ALL,
@@ -62,14 +110,28 @@ impl Config {
                 (b'W', w.inv()),
             ]),
             auto_groups: false,
+            utf8: false,
+            multiline: false,
+            case_insensitive: false,
+            extended: false,
+            simplify: false,
+            engine: Engine::Auto,
+            dfa_states: 10000,
+            lazy_cache: 1 << 20,
+            max_nfa_nodes: 5000,
+            max_dfa_states: 1000000,
+            max_memory: 1 << 30,
         }
     }
+}
 
+impl Config {
     /// check if config is valid
     pub fn is_valid(&self) -> bool {
         for (c, _) in self.esc_charset.iter() {
             match c {
-                b'B'..=b'W' | b'Y' | b'a'..=b'w' | b'y' | b'z' => (),
+                b'u' if self.utf8 => return false,
+                b'C'..=b'W' | b'Y' | b'a' | b'c'..=b'w' | b'y' | b'z' => (),
                 _ => return false,
             }
         }
@@ -0,0 +1,148 @@
+/*
+ * Error diagnostics
+ *
+ * - the lexer keeps the span of the last token, parsers put it next to the error into Diagnostic:
+ *   a broken token spans the bytes read so far, the end of the pattern is an empty span
+ * - render prints the line of the pattern with the span underlined by carets, the message and a hint
+ */
+
+/// error with the span of the pattern, where it is found
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub error: Error,
+    /// none for errors of limits and config, that do not depend on a token
+    pub span: Option<std::ops::Range<usize>>,
+}
+
+impl From<Error> for Diagnostic {
+    fn from(error: Error) -> Diagnostic {
+        Diagnostic { error, span: None }
+    }
+}
+
+/// drop the span
+impl From<Diagnostic> for Error {
+    fn from(d: Diagnostic) -> Error {
+        d.error
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.span {
+            Some(span) => write!(f, "{} at offset {}", self.error, span.start),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl Error {
+    /// error at a span of the pattern
+    pub fn at(self, span: std::ops::Range<usize>) -> Diagnostic {
+        Diagnostic {
+            error: self,
+            span: Some(span),
+        }
+    }
+
+    /// how to fix the error
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Error::Config => "escape charsets may only be defined for letters, except A, Z, b, B, x, X (and u in utf-8 mode)",
+            Error::Charset => "close charset with ']', range bounds must be in ascending order",
+            Error::Escape => "use a known escape sequence, or escape a special char, e.g. '\\(' or '\\x2a'",
+            Error::Repeat => "use {n}, {n,}, {,m} or {n,m}, where 0 < m and n <= m",
+            Error::Overflow => "use numbers from 0 to 255",
+            Error::Balance => "each '(' needs a matching ')'",
+            Error::Group => "each \\Z needs a matching \\A before it",
+            Error::Union => "both sides of '|' must be non-empty, each ')' needs a matching '('",
+            Error::Empty => "put an expression here",
+            Error::Postfix => "'*', '+', '?' and '{...}' must follow an expression",
+            Error::Utf8 => "pattern must be valid utf-8, code points must be valid unicode scalars",
+            Error::Flag => "known inline flags are i, m, s, x, e.g. (?i) or (?x-i:...)",
+            Error::Name => "group names are [A-Za-z_][A-Za-z0-9_]*, names and group indices must be unique",
+            Error::TooLarge => "raise limits in Config, or use smaller repeat counts",
+        }
+    }
+}
+
+impl Diagnostic {
+    /// the line of `pattern` with the error span underlined, the message and the hint
+    pub fn render(&self, pattern: &[u8]) -> String {
+        let msg = format!("error: {}\nhint: {}\n", self.error, self.error.hint());
+        let Some(span) = &self.span else {
+            return msg;
+        };
+        let start = span.start.min(pattern.len());
+        let line_start = pattern[..start]
+            .iter()
+            .rposition(|c| *c == b'\n')
+            .map_or(0, |i| i + 1);
+        let line_end = pattern[start..]
+            .iter()
+            .position(|c| *c == b'\n')
+            .map_or(pattern.len(), |i| start + i);
+        let end = span.end.clamp(start, line_end);
+        let width = |s: &[u8]| String::from_utf8_lossy(s).chars().count();
+        format!(
+            "{}\n{}{}\n{msg}",
+            String::from_utf8_lossy(&pattern[line_start..line_end]),
+            " ".repeat(width(&pattern[line_start..start])),
+            "^".repeat(width(&pattern[start..end]).max(1)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test_diagnostic {
+    use super::*;
+
+    fn err(s: &[u8]) -> Diagnostic {
+        compile(s, Config::default()).err().unwrap()
+    }
+
+    #[test]
+    fn spans() {
+        assert_eq!(err(b"ab{2,1}c"), Error::Repeat.at(2..7));
+        assert_eq!(err(b"a[b-a]"), Error::Charset.at(1..5));
+        assert_eq!(err(b"x(?q)"), Error::Flag.at(1..4));
+        assert_eq!(err(b"(a|b"), Error::Balance.at(4..4));
+        assert_eq!(err(b"a)b"), Error::Balance.at(1..2));
+        assert_eq!(err(b"a|*"), Error::Postfix.at(2..3));
+        assert_eq!(err(b"a{300}"), Error::Overflow.at(1..5));
+        assert_eq!(err(b"(?<x>a)(?<x>b)"), Error::Name.at(7..12));
+        assert_eq!(err(b"a{200}{200}").span, Some(6..11));
+        let simple = Config {
+            simplify: true,
+            ..Config::default()
+        };
+        for s in [&b"ab{2,1}c"[..], b"(a|b", b"(?<x>a)(?<x>b)", b"a{200}{200}"] {
+            assert_eq!(compile(s, simple.clone()).err(), Some(err(s)));
+        }
+        assert_eq!(Diagnostic::from(Error::Config).span, None);
+        assert_eq!(err(b"a{2,1}").to_string(), "bad repeat syntax at offset 1");
+    }
+
+    #[test]
+    fn render() {
+        assert_eq!(
+            err(b"ab{2,1}c").render(b"ab{2,1}c"),
+            "ab{2,1}c\n  ^^^^^\nerror: bad repeat syntax\nhint: use {n}, {n,}, {,m} or {n,m}, where 0 < m and n <= m\n"
+        );
+        assert_eq!(
+            err(b"(a|b").render(b"(a|b"),
+            "(a|b\n    ^\nerror: bad () balance\nhint: each '(' needs a matching ')'\n"
+        );
+        let config = Config {
+            extended: true,
+            ..Config::default()
+        };
+        let s = "é # comment\n  [b-a]".as_bytes();
+        let e = compile(s, config).err().unwrap();
+        assert!(e.render(s).starts_with("  [b-a]\n  ^^^^\n"));
+        assert_eq!(
+            Diagnostic::from(Error::Config).render(b"a"),
+            format!("error: {}\nhint: {}\n", Error::Config, Error::Config.hint())
+        );
+    }
+}
@@ -0,0 +1,369 @@
+/*
+ * Lazy DFA
+ *
+ * - states of subset construction (sets of nfa nodes and look behind, as in build_dfa) are built
+ *   on the first transition to them, while matching; transitions are cached in a table
+ * - cache holds at most `limit` bytes (approximately): once a new state does not fit, cache is flushed,
+ *   the run continues from the new state
+ * - runs follow cached transitions under a read lock, new states are built outside the lock and inserted
+ *   under a write lock, so that threads sharing a regex or a scanner don't wait for each other
+ * - states accept the first rule among accepted ones, for each look ahead
+ */
+
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// transition of lazy dfa, that is not computed yet
+pub const UNKNOWN: usize = usize::MAX - 1;
+
+pub struct LazyDFA {
+    nfa: NFA,
+    /// first byte of each class
+    reps: Vec<u8>,
+    /// max size of cache in bytes
+    limit: usize,
+    /// behind a lock, so that compiled regexes and scanners can be shared between threads
+    cache: RwLock<LazyCache>,
+}
+
+struct LazyCache {
+    subsets: Subsets,
+    states: Vec<(HashSet<usize>, usize)>,
+    /// nodes[n][class] is the next state, DEAD or UNKNOWN
+    nodes: Vec<Vec<usize>>,
+    accept: Vec<Vec<Option<usize>>>,
+    /// start state for each look behind, if cached
+    start: Vec<Option<usize>>,
+    /// approximate size in bytes
+    size: usize,
+    flushes: usize,
+}
+
+pub fn build_lazy_dfa(nfa: NFA, limit: usize) -> LazyDFA {
+    LazyDFA {
+        reps: nfa.classes.reps(),
+        cache: RwLock::new(LazyCache::new(nfa.has_asserts(), 0)),
+        nfa,
+        limit,
+    }
+}
+
+impl LazyCache {
+    fn new(asserts: bool, flushes: usize) -> LazyCache {
+        LazyCache {
+            subsets: Subsets::new(asserts),
+            states: vec![],
+            nodes: vec![],
+            accept: vec![],
+            start: vec![None; LOOKS],
+            size: 0,
+            flushes,
+        }
+    }
+
+    fn flush(&mut self) {
+        *self = LazyCache::new(self.subsets.asserts, self.flushes + 1);
+    }
+}
+
+impl LazyDFA {
+    pub fn classes(&self) -> &ByteClasses {
+        &self.nfa.classes
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, LazyCache> {
+        if self.cache.is_poisoned() {
+            drop(self.write());
+        }
+        self.cache.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, LazyCache> {
+        // a run, that panicked, may leave the cache half updated: start over
+        self.cache.write().unwrap_or_else(|e| {
+            self.cache.clear_poison();
+            let mut cache = e.into_inner();
+            cache.flush();
+            cache
+        })
+    }
+
+    /// number of cached states
+    pub fn states(&self) -> usize {
+        self.read().states.len()
+    }
+
+    /// number of times the cache was flushed
+    pub fn flushes(&self) -> usize {
+        self.read().flushes
+    }
+
+    /// first rule, accepted by the state for each look ahead
+    fn accept(&self, set: &HashSet<usize>, behind: usize) -> Vec<Option<usize>> {
+        (0..LOOKS)
+            .map(|look| {
+                let closure = self.nfa.closure(set, behind, look);
+                closure
+                    .iter()
+                    .flat_map(|n| self.nfa.nodes[*n].accept.iter())
+                    .min()
+                    .copied()
+            })
+            .collect()
+    }
+
+    /// find state by its set and look ahead, that leads to it, or create a new one, flush the cache, if it is full;
+    /// `link(cache, id)` records the transition to the state; the cache is left read locked
+    ///
+    /// the new state is built outside the lock, so that other runs go on meanwhile
+    fn state(
+        &self,
+        set: HashSet<usize>,
+        look: usize,
+        link: impl FnOnce(&mut LazyCache, usize),
+    ) -> (RwLockReadGuard<'_, LazyCache>, usize) {
+        let kernel = sorted(&set);
+        let (behind, known) = {
+            let cache = self.read();
+            let known = cache.subsets.get(&kernel, look).is_some();
+            (cache.subsets.behind(look), known)
+        };
+        let accept = (!known).then(|| self.accept(&set, behind));
+        let mut cache = self.write();
+        let id = match cache.subsets.get(&kernel, look) {
+            Some(id) => id,
+            None => {
+                // the set, its kernel in the index of subsets, transitions, accept and headers
+                let word = size_of::<usize>();
+                let size = set.capacity() * (word + 1)
+                    + (kernel.len() + 4) * word
+                    + (self.nfa.classes.count + 2 * LOOKS + 8) * word;
+                if cache.size + size > self.limit {
+                    cache.flush();
+                }
+                let id = cache.subsets.insert(kernel, look);
+                cache.nodes.push(vec![UNKNOWN; self.nfa.classes.count]);
+                // the state was cached, but another run flushed the cache meanwhile
+                cache
+                    .accept
+                    .push(accept.unwrap_or_else(|| self.accept(&set, behind)));
+                cache.states.push((set, behind));
+                cache.size += size;
+                id
+            }
+        };
+        link(&mut cache, id);
+        (RwLockWriteGuard::downgrade(cache), id)
+    }
+
+    fn start(&self, behind: usize) -> (RwLockReadGuard<'_, LazyCache>, usize) {
+        let cache = self.read();
+        if let Some(id) = cache.start[behind] {
+            return (cache, id);
+        }
+        drop(cache);
+        self.state(HashSet::from([self.nfa.begin]), behind, |cache, id| {
+            cache.start[behind] = Some(id)
+        })
+    }
+
+    fn next<'a>(
+        &'a self,
+        cache: RwLockReadGuard<'a, LazyCache>,
+        n: usize,
+        class: usize,
+    ) -> (RwLockReadGuard<'a, LazyCache>, usize) {
+        let next = cache.nodes[n][class];
+        if next != UNKNOWN {
+            return (cache, next);
+        }
+        let (set, prev) = cache.states[n].clone();
+        let flushes = cache.flushes;
+        drop(cache);
+        let look = self.nfa.classes.look[class];
+        let mut to: HashSet<usize> = HashSet::new();
+        for m in self.nfa.closure(&set, prev, look) {
+            if let Some(s) = self.nfa.nodes[m].edges.get(&self.reps[class]) {
+                to.extend(s);
+            }
+        }
+        // after flush, state n is gone
+        let link = move |cache: &mut LazyCache, id: usize| {
+            if cache.flushes == flushes {
+                cache.nodes[n][class] = id;
+            }
+        };
+        if to.is_empty() {
+            let mut cache = self.write();
+            link(&mut cache, DEAD);
+            return (RwLockWriteGuard::downgrade(cache), DEAD);
+        }
+        self.state(to, look, link)
+    }
+
+    /// run from the start state for look behind `behind` over `bytes`, till the dead state;
+    /// `accept(i, rule)` is called for each state after i bytes, that accepts with look ahead `ahead(i)`
+    pub fn run(
+        &self,
+        behind: usize,
+        bytes: impl Iterator<Item = u8>,
+        ahead: impl Fn(usize) -> usize,
+        mut accept: impl FnMut(usize, usize),
+    ) {
+        let (mut cache, mut state) = self.start(behind);
+        if let Some(r) = cache.accept[state][ahead(0)] {
+            accept(0, r);
+        }
+        for (i, c) in bytes.enumerate() {
+            (cache, state) = self.next(cache, state, self.nfa.classes.get(c));
+            if state == DEAD {
+                break;
+            }
+            if let Some(r) = cache.accept[state][ahead(i + 1)] {
+                accept(i + 1, r);
+            }
+        }
+    }
+
+    /// longest match at s[pos..]: (end, rule)
+    pub fn longest_at(&self, s: &[u8], pos: usize) -> Option<(usize, usize)> {
+        let classes = &self.nfa.classes;
+        let mut res = None;
+        self.run(
+            classes.look(s[..pos].last().copied()),
+            s[pos..].iter().copied(),
+            |i| classes.look(s.get(pos + i).copied()),
+            |i, r| res = Some((pos + i, r)),
+        );
+        res
+    }
+
+    /// starts[i] is true, if the reversed dfa accepts at s[i..], reading `s` from the end
+    pub fn starts(&self, s: &[u8]) -> Vec<bool> {
+        let classes = &self.nfa.classes;
+        let mut starts = vec![false; s.len() + 1];
+        self.run(
+            LOOK_EDGE,
+            s.iter().rev().copied(),
+            |i| classes.look(s[..s.len() - i].last().copied()),
+            |i, _| starts[s.len() - i] = true,
+        );
+        starts
+    }
+}
+
+#[cfg(test)]
+mod test_lazy {
+    use super::*;
+
+    fn lazy(s: &[u8], limit: usize) -> LazyDFA {
+        build_lazy_dfa(build_nfa(Lexer::new(s, Config::default())).unwrap(), limit)
+    }
+
+    #[test]
+    fn just_works() {
+        let dfa = lazy(b"[a-z]+|x\\d", 1 << 20);
+        assert_eq!(dfa.states(), 0);
+        assert_eq!(dfa.longest_at(b"abc1", 0), Some((3, 0)));
+        assert_eq!(dfa.longest_at(b"_x1", 1), Some((3, 0)));
+        assert_eq!(dfa.longest_at(b"1", 0), None);
+        assert!(dfa.states() <= 5);
+        let states = dfa.states();
+        assert_eq!(dfa.longest_at(b"abc1", 0), Some((3, 0)));
+        assert_eq!(dfa.states(), states);
+        assert_eq!(dfa.flushes(), 0);
+
+        let rules = [&b"if"[..], b"[a-z]+"].map(|s| Lexer::new(s, Config::default()));
+        let dfa = build_lazy_dfa(build_nfa_rules(rules.into()).unwrap(), 1 << 20);
+        assert_eq!(dfa.longest_at(b"if", 0), Some((2, 0)));
+        assert_eq!(dfa.longest_at(b"ifx", 0), Some((3, 1)));
+    }
+
+    #[test]
+    fn flush() {
+        let dfa = lazy(b"(a|b)*a(a|b){6}", 1024);
+        let s = b"abbbbbbbbbbabbbbbbbbbbbbbabaaaabbbbab";
+        let full = build_dfa(build_nfa(Lexer::new(b"(a|b)*a(a|b){6}", Config::default())).unwrap());
+        let mut state = full.start[LOOK_EDGE];
+        let mut ends = vec![];
+        for (i, c) in s.iter().enumerate() {
+            state = full.next(state, *c).unwrap();
+            if !full.accept[state][LOOK_EDGE].is_empty() {
+                ends.push(i + 1);
+            }
+        }
+        let mut lazy_ends = vec![];
+        dfa.run(
+            LOOK_EDGE,
+            s.iter().copied(),
+            |_| LOOK_EDGE,
+            |i, _| lazy_ends.push(i),
+        );
+        assert_eq!(lazy_ends, ends);
+        assert!(dfa.flushes() > 0);
+    }
+
+    #[test]
+    fn regex() {
+        let config = Config {
+            engine: Engine::LazyDFA,
+            auto_groups: true,
+            ..Config::default()
+        };
+        let re = compile(b"(\\b[a-z]+)(\\d*)", config).unwrap();
+        assert!(re.lazy.is_some() && re.nodes.is_empty());
+        assert_eq!(re.match_len(b"ab12 c"), Some(4));
+        assert_eq!(
+            re.captures(b"ab12"),
+            Some(vec![(Some(0), Some(2)), (Some(2), Some(4))])
+        );
+        assert_eq!(re.find_iter(b"1a b2 3c").collect::<Vec<_>>(), vec![(3, 5)]);
+    }
+
+    #[test]
+    fn shared() {
+        fn is_sync<T: Send + Sync>() {}
+        is_sync::<LazyDFA>();
+        is_sync::<Regex>();
+        is_sync::<crate::lex::Scanner<usize>>();
+        let dfa = lazy(b"[a-z]+\\d", 1 << 20);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| assert_eq!(dfa.longest_at(b"abc1", 0), Some((4, 0))));
+            }
+        });
+
+        // runs flush the cache under each other
+        let dfa = lazy(b"(a|b)*a(a|b){6}", 1024);
+        let s = b"abbbbbbbbbbabbbbbbbbbbbbbabaaaabbbbab";
+        let ends = |dfa: &LazyDFA| {
+            let mut ends = vec![];
+            dfa.run(
+                LOOK_EDGE,
+                s.iter().copied(),
+                |_| LOOK_EDGE,
+                |i, _| ends.push(i),
+            );
+            ends
+        };
+        let expected = ends(&lazy(b"(a|b)*a(a|b){6}", 1 << 20));
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..20 {
+                        assert_eq!(ends(&dfa), expected);
+                    }
+                });
+            }
+        });
+        assert!(dfa.flushes() > 0);
+    }
+
+    #[test]
+    fn anchors() {
+        let dfa = lazy(b"^a|\\bb", 1 << 20);
+        assert_eq!(dfa.longest_at(b"aa", 0), Some((1, 0)));
+        assert_eq!(dfa.longest_at(b"aa", 1), None);
+        assert_eq!(dfa.longest_at(b"ab", 1), None);
+        assert_eq!(dfa.longest_at(b" b", 1), Some((2, 0)));
+    }
+}
@@ -4,9 +4,12 @@ pub enum Token {
     Open,                       // )
     Union,                      // |
     StartGroup,                 // \A
+    NamedGroup(String),         // (?<name>
     EndGroup,                   // \Z
     Repeat((u32, Option<u32>)), // + * ? {...}
+    Assert(Assert),             // ^ $ \b \B
     Char(Charset),
+    Unicode(UnicodeSet), // non-ascii chars in utf-8 mode
 }
 
 pub struct Lexer<'a> {
@@ -14,6 +17,12 @@ pub struct Lexer<'a> {
     peekc: Option<u8>,
     peek: Option<Token>,
     config: Config,
+    /// config of each enclosing group and whether it captures (ends with EndGroup),
+    /// inline flags change config till the end of the group
+    scopes: Vec<(Config, bool)>,
+    /// length of the pattern and offset of the current token
+    len: usize,
+    start: usize,
 }
 
 impl Lexer<'_> {
@@ -23,40 +32,186 @@ impl Lexer<'_> {
             peekc: None,
             peek: None,
             config,
+            scopes: Vec::new(),
+            len: s.len(),
+            start: 0,
         }
     }
 
+    /// offset of the next byte within the pattern
+    fn offset(&self) -> usize {
+        self.len - self.it.len() - self.peekc.is_some() as usize
+    }
+
+    /// span of the last token within the pattern (read so far, if it is broken)
+    pub fn span(&self) -> std::ops::Range<usize> {
+        self.start..self.offset()
+    }
+
     pub fn token(&mut self) -> Result<Token> {
         if self.peek.is_some() {
-            return Ok(core::mem::replace(&mut self.peek, None).unwrap());
+            return Ok(self.peek.take().unwrap());
         }
+        self.skip_extended();
+        self.start = self.offset();
         match self.char() {
-            Some(b'(') => match self.config.auto_groups {
-                true => {
-                    self.peek = Some(Token::Open);
-                    Ok(Token::StartGroup)
+            Some(b'(') => {
+                match self.char() {
+                    Some(b'?') => return self.flags(),
+                    c => self.peekc = c,
                 }
-                false => Ok(Token::Open),
-            },
+                self.scopes
+                    .push((self.config.clone(), self.config.auto_groups));
+                match self.config.auto_groups {
+                    true => {
+                        self.peek = Some(Token::Open);
+                        Ok(Token::StartGroup)
+                    }
+                    false => Ok(Token::Open),
+                }
+            }
             Some(b')') => {
-                if self.config.auto_groups {
+                let capture = match self.scopes.pop() {
+                    Some((config, capture)) => {
+                        self.config = config;
+                        capture
+                    }
+                    None => self.config.auto_groups,
+                };
+                if capture {
                     self.peek = Some(Token::EndGroup);
                 }
                 Ok(Token::Close(false))
             }
             Some(b'|') => Ok(Token::Union),
-            Some(b'.') => Ok(Token::Char(self.config.dot_charset.clone())),
+            Some(b'^') => Ok(Token::Assert(match self.config.multiline {
+                true => Assert::LineStart,
+                false => Assert::TextStart,
+            })),
+            Some(b'$') => Ok(Token::Assert(match self.config.multiline {
+                true => Assert::LineEnd,
+                false => Assert::TextEnd,
+            })),
+            Some(b'.') => Ok(self.set(self.config.dot_charset.clone())),
             Some(b'*') => Ok(Token::Repeat((0, None))),
             Some(b'+') => Ok(Token::Repeat((1, None))),
             Some(b'?') => Ok(Token::Repeat((0, Some(1)))),
-            Some(b'[') => Ok(Token::Char(self.charset()?)),
+            Some(b'[') => self.charset(),
             Some(b'{') => self.repeat(),
-            Some(b'\\') => self.escape(),
-            Some(c) => Ok(Token::Char(charset!(c))),
+            Some(b'\\') => self.escape_assert(),
+            Some(c) => {
+                let c = self.scalar(c)?;
+                Ok(self.single(c))
+            }
             None => Ok(Token::Close(true)),
         }
     }
 
+    /// skip whitespace and comments in extended mode
+    fn skip_extended(&mut self) {
+        while self.config.extended {
+            match self.char() {
+                Some(b' ' | b'\t' | b'\n' | b'\r' | 0x0b | 0x0c) => (),
+                Some(b'#') => while !matches!(self.char(), Some(b'\n') | None) {},
+                c => {
+                    self.peekc = c;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// (?flags) changes config till the end of the enclosing group, then the next token is returned
+    /// (?flags:...) is a non-capturing group with its own config
+    /// flags are i, m, s, x, flags after '-' are turned off
+    fn flags(&mut self) -> Result<Token> {
+        match self.char() {
+            Some(b'<') => return self.named_group(),
+            c => self.peekc = c,
+        }
+        let saved = self.config.clone();
+        let (mut on, mut empty) = (true, true);
+        loop {
+            match self.char() {
+                Some(b'i') => self.config.case_insensitive = on,
+                Some(b'm') => self.config.multiline = on,
+                Some(b's') if on => self.config.dot_charset.add_char(b'\n'),
+                Some(b's') => self.config.dot_charset.remove_char(b'\n'),
+                Some(b'x') => self.config.extended = on,
+                Some(b'-') if on => {
+                    on = false;
+                    empty = true;
+                    continue;
+                }
+                Some(b')') if !empty => return self.token(),
+                Some(b':') if !empty || on => {
+                    self.scopes.push((saved, false));
+                    return Ok(Token::Open);
+                }
+                _ => return Err(Error::Flag),
+            }
+            empty = false;
+        }
+    }
+
+    /// (?<name>...) is a capture group regardless of auto_groups, name is [A-Za-z_][A-Za-z0-9_]*
+    fn named_group(&mut self) -> Result<Token> {
+        let mut name = String::new();
+        loop {
+            match self.char() {
+                Some(b'>') if !name.is_empty() => break,
+                Some(c) if c.is_ascii_alphabetic() || c == b'_' => name.push(c as char),
+                Some(c) if c.is_ascii_digit() && !name.is_empty() => name.push(c as char),
+                _ => return Err(Error::Name),
+            }
+        }
+        self.scopes.push((self.config.clone(), true));
+        self.peek = Some(Token::Open);
+        Ok(Token::NamedGroup(name))
+    }
+
+    /// char token for a byte charset
+    fn set(&self, c: Charset) -> Token {
+        self.unicode(UnicodeSet::from_charset(&c, self.config.utf8))
+    }
+
+    /// char token for a set of code points, with both cases in case-insensitive mode
+    fn unicode(&self, s: UnicodeSet) -> Token {
+        let s = match self.config.case_insensitive {
+            true => s.fold(self.config.utf8),
+            false => s,
+        };
+        match s.last() {
+            Some(c) if c >= 0x80 && self.config.utf8 => Token::Unicode(s),
+            _ => Token::Char(s.to_charset().unwrap()),
+        }
+    }
+
+    fn single(&self, c: u32) -> Token {
+        let mut s = UnicodeSet::new();
+        s.add_char(c);
+        self.unicode(s)
+    }
+
+    /// read the rest of utf-8 sequence, starting with `c`, in utf-8 mode
+    fn scalar(&mut self, c: u8) -> Result<u32> {
+        let len = match c {
+            _ if !self.config.utf8 || c < 0x80 => return Ok(c as u32),
+            0xC2..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF4 => 4,
+            _ => return Err(Error::Utf8),
+        };
+        let mut buf = vec![c];
+        for _ in 1..len {
+            buf.push(self.char().ok_or(Error::Utf8)?);
+        }
+        match std::str::from_utf8(&buf) {
+            Ok(s) => Ok(s.chars().next().unwrap() as u32),
+            Err(_) => Err(Error::Utf8),
+        }
+    }
+
     fn char(&mut self) -> Option<u8> {
         match self.peekc {
             Some(c) => {
@@ -72,7 +227,7 @@ impl Lexer<'_> {
         let min_int = min.unwrap_or(0) as u32;
         match self.char() {
             Some(b',') => (),
-            Some(b'}') if min != None && min != Some(0) => {
+            Some(b'}') if min.is_some() && min != Some(0) => {
                 return Ok(Token::Repeat((min_int, Some(min_int))))
             }
             _ => return Err(Error::Repeat),
@@ -81,18 +236,12 @@ impl Lexer<'_> {
         if self.char() != Some(b'}') || max == Some(0) || min_int > max.unwrap_or(255) as u32 {
             return Err(Error::Repeat);
         }
-        Ok(Token::Repeat((
-            min_int,
-            match max {
-                Some(c) => Some(c as u32),
-                None => None,
-            },
-        )))
+        Ok(Token::Repeat((min_int, max.map(|c| c as u32))))
     }
 
-    fn charset(&mut self) -> Result<Charset> {
-        let mut s = charset!();
-        let mut prev: Option<u8> = None;
+    fn charset(&mut self) -> Result<Token> {
+        let mut s = UnicodeSet::new();
+        let mut prev: Option<u32> = None;
         let inv = match self.char() {
             Some(b'^') => true,
             c => {
@@ -103,21 +252,29 @@ impl Lexer<'_> {
         loop {
             match self.char() {
                 None => return Err(Error::Charset),
-                Some(b']') if !s.empty() => return Ok(if inv { s.inv() } else { s }),
+                Some(b']') if !s.empty() => {
+                    let max = if self.config.utf8 { MAX_SCALAR } else { 255 };
+                    // fold before inversion, so that [^a] matches neither a nor A
+                    let s = match self.config.case_insensitive {
+                        true => s.fold(self.config.utf8),
+                        false => s,
+                    };
+                    return Ok(self.unicode(if inv { s.inv(max) } else { s }));
+                }
                 Some(b'\\') => {
                     let p = self.char_escape()?;
-                    prev = p.iter().next();
+                    prev = p.first();
                     s.add(&p);
                 }
-                Some(b'-') if prev != None => {
+                Some(b'-') if prev.is_some() => {
                     let end = match self.char() {
-                        Some(b'\\') => self.char_escape()?.iter().next_back().unwrap(),
+                        Some(b'\\') => self.char_escape()?.last().unwrap(),
                         Some(b']') => {
                             self.peekc = Some(b']');
-                            s.add_char(b'-');
+                            s.add_char(b'-' as u32);
                             continue;
                         }
-                        Some(c) => c,
+                        Some(c) => self.scalar(c)?,
                         None => return Err(Error::Charset),
                     };
                     let begin = prev.unwrap();
@@ -128,6 +285,7 @@ impl Lexer<'_> {
                     s.add_range(begin, end);
                 }
                 Some(c) => {
+                    let c = self.scalar(c)?;
                     prev = Some(c);
                     s.add_char(c);
                 }
@@ -135,13 +293,30 @@ impl Lexer<'_> {
         }
     }
 
-    fn char_escape(&mut self) -> Result<Charset> {
+    fn char_escape(&mut self) -> Result<UnicodeSet> {
         match self.escape()? {
-            Token::Char(c) => Ok(c),
+            Token::Char(c) => Ok(UnicodeSet::from_charset(&c, self.config.utf8)),
+            Token::Unicode(s) => Ok(s),
             _ => Err(Error::Escape),
         }
     }
 
+    /// escape outside of charsets, it may be a word assertion
+    fn escape_assert(&mut self) -> Result<Token> {
+        let x = match self.char() {
+            Some(b'b') => Assert::WordBoundary,
+            Some(b'B') => Assert::NotWordBoundary,
+            c => {
+                self.peekc = c;
+                return self.escape();
+            }
+        };
+        match self.config.esc_charset.contains_key(&b'w') {
+            true => Ok(Token::Assert(x)),
+            false => Err(Error::Escape),
+        }
+    }
+
     fn escape(&mut self) -> Result<Token> {
         match self.char() {
             None => Err(Error::Escape),
@@ -149,15 +324,43 @@ impl Lexer<'_> {
             Some(b'Z') => Ok(Token::EndGroup),
             Some(b'x') | Some(b'X') => match self.atoi(16, 2)? {
                 None => Err(Error::Escape),
-                Some(c) => Ok(Token::Char(charset!(c))),
+                Some(c) => Ok(self.single(c as u32)),
             },
+            Some(b'u') if self.config.utf8 => {
+                let c = self.codepoint()?;
+                Ok(self.single(c))
+            }
             Some(c) => match self.config.esc_charset.get(&c) {
-                None => Ok(Token::Char(charset!(c))),
-                Some(c) => return Ok(Token::Char(c.clone())),
+                None => {
+                    let c = self.scalar(c)?;
+                    Ok(self.single(c))
+                }
+                Some(c) => Ok(self.set(c.clone())),
             },
         }
     }
 
+    /// \u{...}
+    fn codepoint(&mut self) -> Result<u32> {
+        if self.char() != Some(b'{') {
+            return Err(Error::Escape);
+        }
+        let mut res: u32 = 0;
+        for i in 0..7 {
+            match self.char() {
+                Some(b'}') if i != 0 => {
+                    return match char::from_u32(res) {
+                        Some(_) => Ok(res),
+                        None => Err(Error::Utf8),
+                    }
+                }
+                Some(c) if Self::digit(c) < 16 && i != 6 => res = res * 16 + Self::digit(c) as u32,
+                _ => break,
+            }
+        }
+        Err(Error::Escape)
+    }
+
     fn atoi(&mut self, base: u8, count: usize) -> Result<Option<u8>> {
         let mut res: Option<u8> = None;
         for _ in 0..count {
@@ -193,7 +396,7 @@ impl Lexer<'_> {
 mod test_lexer {
     use super::*;
 
-    fn lexer(s: &[u8]) -> Lexer {
+    fn lexer(s: &[u8]) -> Lexer<'_> {
         return Lexer::new(s, Config::default());
     }
 
@@ -238,9 +441,10 @@ mod test_lexer {
         }
 
         let mut v: Vec<u8> = vec![];
-        for c in 0..128 {
+        for c in 0..=255 {
             match c {
-                b'(' | b')' | b'{' | b'*' | b'+' | b'?' | b'|' | b'[' | b'.' | b'\\' => {
+                b'(' | b')' | b'{' | b'*' | b'+' | b'?' | b'|' | b'[' | b'.' | b'\\' | b'^'
+                | b'$' => {
                     v.push(b'\\');
                     v.push(c);
                 }
@@ -248,7 +452,7 @@ mod test_lexer {
             }
         }
         let mut lex = lexer(&v);
-        for c in 0..128 {
+        for c in 0..=255 {
             assert_eq!(lex.token().unwrap(), Token::Char(charset!(c)));
         }
         assert_eq!(lex.token().unwrap(), Token::Close(true));
@@ -256,7 +460,8 @@ mod test_lexer {
 
     #[test]
     fn charset() {
-        let mut lex = lexer(b"[][\\]][^]][\\d][^-\\x05][\\s-\\d][--][abc\\n]");
+        let mut lex =
+            lexer(b"[][\\]][^]][\\d][^-\\x05][\\s-\\d][--][abc\\n][\\x80-\\xff][\\xfe\xff]");
         let ans = [
             Token::Char(charset!(b'[', b']')),
             Token::Char(charset!(b']').inv()),
@@ -265,6 +470,8 @@ mod test_lexer {
             Token::Char(charset!([b'\t', b'9'])),
             Token::Char(charset!(b'-')),
             Token::Char(charset!([b'a', b'c']; b'\n')),
+            Token::Char(charset!([0x80, 0xff])),
+            Token::Char(charset!(0xfe, 0xff)),
             Token::Close(true),
         ];
         for a in ans {
@@ -287,9 +494,94 @@ mod test_lexer {
     }
 
     #[test]
-    fn auto_groups() {
+    fn anchors() {
+        let mut lex = lexer(b"^a$\\^\\$[$^]");
+        let ans = [
+            Token::Assert(Assert::TextStart),
+            Token::Char(charset!(b'a')),
+            Token::Assert(Assert::TextEnd),
+            Token::Char(charset!(b'^')),
+            Token::Char(charset!(b'$')),
+            Token::Char(charset!(b'$', b'^')),
+            Token::Close(true),
+        ];
+        for a in ans {
+            assert_eq!(lex.token().unwrap(), a);
+        }
+
+        let config = Config {
+            multiline: true,
+            ..Config::default()
+        };
+        let mut lex = Lexer::new(b"^$", config);
+        assert_eq!(lex.token(), Ok(Token::Assert(Assert::LineStart)));
+        assert_eq!(lex.token(), Ok(Token::Assert(Assert::LineEnd)));
+
+        let mut lex = lexer(b"\\b\\B[\\b]");
+        assert_eq!(lex.token(), Ok(Token::Assert(Assert::WordBoundary)));
+        assert_eq!(lex.token(), Ok(Token::Assert(Assert::NotWordBoundary)));
+        assert_eq!(lex.token(), Ok(Token::Char(charset!(b'b'))));
+
         let mut config = Config::default();
-        config.auto_groups = true;
+        config.esc_charset.remove(&b'w');
+        assert_eq!(Lexer::new(b"\\b", config).token(), Err(Error::Escape));
+    }
+
+    #[test]
+    fn utf8() {
+        let config = Config {
+            utf8: true,
+            ..Config::default()
+        };
+        let unicode = |r: &[(u32, u32)]| {
+            let mut s = UnicodeSet::new();
+            for (a, b) in r {
+                s.add_range(*a, *b);
+            }
+            Token::Unicode(s)
+        };
+        let mut lex = Lexer::new("aé[α-ω\\d][a-c]\\u{1F600}\\xe9[^a]\\D".as_bytes(), config);
+        let ans = [
+            Token::Char(charset!(b'a')),
+            unicode(&[(0xE9, 0xE9)]),
+            unicode(&[(0x30, 0x39), (0x3B1, 0x3C9)]),
+            Token::Char(charset!([b'a', b'c'])),
+            unicode(&[(0x1F600, 0x1F600)]),
+            unicode(&[(0xE9, 0xE9)]),
+            unicode(&[(0, 0x60), (0x62, 0xD7FF), (0xE000, MAX_SCALAR)]),
+            unicode(&[(0, 0x2F), (0x3A, MAX_SCALAR)]),
+            Token::Close(true),
+        ];
+        for a in ans {
+            assert_eq!(lex.token().unwrap(), a);
+        }
+
+        let config = Config {
+            utf8: true,
+            ..Config::default()
+        };
+        for s in [
+            &b"\xff"[..],
+            b"\xc3",
+            b"[\xc3]",
+            b"\\u{d800}",
+            b"\\u{110000}",
+        ] {
+            assert_eq!(Lexer::new(s, config.clone()).token(), Err(Error::Utf8));
+        }
+        for s in [&b"\\u"[..], b"\\u{}", b"\\u{1234567}", b"\\u{g}"] {
+            assert_eq!(Lexer::new(s, config.clone()).token(), Err(Error::Escape));
+        }
+        assert_eq!(onetok(b"\\u"), Ok(Token::Char(charset!(b'u'))));
+        assert_eq!(onetok("é".as_bytes()), Ok(Token::Char(charset!(0xC3))));
+    }
+
+    #[test]
+    fn auto_groups() {
+        let config = Config {
+            auto_groups: true,
+            ..Config::default()
+        };
         let mut lex = Lexer::new(b"()", config);
         assert_eq!(lex.token(), Ok(Token::StartGroup));
         assert_eq!(lex.token(), Ok(Token::Open));
@@ -297,4 +589,167 @@ mod test_lexer {
         assert_eq!(lex.token(), Ok(Token::EndGroup));
         assert_eq!(lex.token(), Ok(Token::Close(true)));
     }
+
+    #[test]
+    fn case_insensitive() {
+        let config = Config {
+            case_insensitive: true,
+            ..Config::default()
+        };
+        let mut lex = Lexer::new(b"a[b-dX][^e]\\d\xe9", config);
+        let ans = [
+            Token::Char(charset!(b'a', b'A')),
+            Token::Char(charset!([b'b', b'd'], [b'B', b'D']; b'x', b'X')),
+            Token::Char(charset!(b'e', b'E').inv()),
+            Token::Char(charset!([b'0', b'9'])),
+            Token::Char(charset!(0xe9)),
+            Token::Close(true),
+        ];
+        for a in ans {
+            assert_eq!(lex.token().unwrap(), a);
+        }
+
+        let mut lex = lexer(b"a((?i)b(c)d)e");
+        let ans = [
+            Token::Char(charset!(b'a')),
+            Token::Open,
+            Token::Char(charset!(b'b', b'B')),
+            Token::Open,
+            Token::Char(charset!(b'c', b'C')),
+            Token::Close(false),
+            Token::Char(charset!(b'd', b'D')),
+            Token::Close(false),
+            Token::Char(charset!(b'e')),
+            Token::Close(true),
+        ];
+        for a in ans {
+            assert_eq!(lex.token().unwrap(), a);
+        }
+        assert_eq!(onetok(b"(?q)"), Err(Error::Flag));
+        assert_eq!(onetok(b"(?i"), Err(Error::Flag));
+
+        let r = compile(b"(?i)hello [^x]orld", Config::default()).unwrap();
+        assert!(r.is_match(b"HeLLo World"));
+        assert!(!r.is_match(b"hello Xorld"));
+        let r = compile(b"a((?i)b)c", Config::default()).unwrap();
+        assert!(r.is_match(b"aBc"));
+        assert!(!r.is_match(b"aBC"));
+
+        let config = Config {
+            utf8: true,
+            case_insensitive: true,
+            ..Config::default()
+        };
+        let r = compile("ωé".as_bytes(), config).unwrap();
+        assert!(r.is_match("ΩÉ".as_bytes()));
+        assert!(r.is_match("ωé".as_bytes()));
+    }
+
+    #[test]
+    fn flag_groups() {
+        let config = Config {
+            auto_groups: true,
+            ..Config::default()
+        };
+        let mut lex = Lexer::new(b"(?i:a(?-i)b)(?s-i:.)(?m:^)c", config);
+        let ans = [
+            Token::Open,
+            Token::Char(charset!(b'a', b'A')),
+            Token::Char(charset!(b'b')),
+            Token::Close(false),
+            Token::Open,
+            Token::Char(Charset::ALL),
+            Token::Close(false),
+            Token::Open,
+            Token::Assert(Assert::LineStart),
+            Token::Close(false),
+            Token::Char(charset!(b'c')),
+            Token::Close(true),
+        ];
+        for a in ans {
+            assert_eq!(lex.token().unwrap(), a);
+        }
+
+        let mut config = Config::default();
+        config.dot_charset.remove_char(b'\n');
+        let mut lex = Lexer::new(b"(?s).(?-s).(?:)", config);
+        assert_eq!(lex.token(), Ok(Token::Char(Charset::ALL)));
+        assert_eq!(lex.token(), Ok(Token::Char(charset!(b'\n').inv())));
+        assert_eq!(lex.token(), Ok(Token::Open));
+
+        let mut lex = lexer(b"(?x) a\t# comment\n\\ [ ]\\#(?-x) ");
+        let ans = [
+            Token::Char(charset!(b'a')),
+            Token::Char(charset!(b' ')),
+            Token::Char(charset!(b' ')),
+            Token::Char(charset!(b'#')),
+            Token::Char(charset!(b' ')),
+            Token::Close(true),
+        ];
+        for a in ans {
+            assert_eq!(lex.token().unwrap(), a);
+        }
+
+        for s in [
+            &b"(?)"[..],
+            b"(?-)",
+            b"(?i-)",
+            b"(?-:a)",
+            b"(?i--s)",
+            b"(?=a)",
+        ] {
+            assert_eq!(onetok(s), Err(Error::Flag));
+        }
+
+        let config = Config {
+            auto_groups: true,
+            ..Config::default()
+        };
+        let r = compile(b"((?i:a)b)", config).unwrap();
+        assert_eq!(r.groups(), 1);
+        assert_eq!(r.captures(b"Ab"), Some(vec![(Some(0), Some(2))]));
+        assert!(!r.is_match(b"AB"));
+    }
+
+    #[test]
+    fn named_groups() {
+        let mut lex = lexer(b"(?<x_1>a(b))");
+        let ans = [
+            Token::NamedGroup(String::from("x_1")),
+            Token::Open,
+            Token::Char(charset!(b'a')),
+            Token::Open,
+            Token::Char(charset!(b'b')),
+            Token::Close(false),
+            Token::Close(false),
+            Token::EndGroup,
+            Token::Close(true),
+        ];
+        for a in ans {
+            assert_eq!(lex.token().unwrap(), a);
+        }
+        for s in [&b"(?<>a)"[..], b"(?<1a>a)", b"(?<a-b>a)", b"(?<a"] {
+            assert_eq!(onetok(s), Err(Error::Name));
+        }
+
+        let r = compile(
+            b"(?<year>[0-9]+)-(?:[0-9]+)-(?<day>[0-9]+)",
+            Config::default(),
+        )
+        .unwrap();
+        assert_eq!(r.groups(), 2);
+        assert_eq!(r.group("day"), Some(1));
+        assert_eq!(r.group("month"), None);
+        assert_eq!(r.capture(b"2024-10-18", "year"), Some((Some(0), Some(4))));
+        assert_eq!(r.capture(b"2024-10-18", "day"), Some((Some(8), Some(10))));
+        assert_eq!(r.capture(b"2024-", "day"), None);
+
+        let config = Config {
+            auto_groups: true,
+            ..Config::default()
+        };
+        let r = compile(b"(a)(?<x>b)", config).unwrap();
+        assert_eq!(r.group("x"), Some(1));
+        assert_eq!(r.capture(b"ab", "x"), Some((Some(1), Some(2))));
+    }
 }
@@ -0,0 +1,207 @@
+/*
+ * Match compiled regex
+ *
+ * - walk dfa tables over the input, starting in the state for look behind
+ * - a match ends in a state, accepting for look ahead
+ * - group spans are extracted by tagged dfa, states head/tail sets are enough to check them
+ */
+
+/// (start, end) offsets of a group, None if group did not participate in the match
+pub type Span = (Option<usize>, Option<usize>);
+
+impl Regex {
+    /// start state for a run from s[start..]
+    fn start(&self, s: &[u8], start: usize) -> usize {
+        self.start[self.classes.look(s[..start].last().copied())]
+    }
+
+    /// states, visited by dfa: i-th state is reached after reading s[start..start + i]
+    /// stops at the first missing transition
+    fn walk(&self, s: &[u8], start: usize) -> Vec<usize> {
+        let mut states = vec![self.start(s, start)];
+        for c in &s[start..] {
+            match next_state(&self.classes, &self.nodes, *states.last().unwrap(), *c) {
+                Some(n) => states.push(n),
+                None => break,
+            }
+        }
+        states
+    }
+
+    /// number of capture groups
+    pub fn groups(&self) -> usize {
+        self.nfa.groups
+    }
+
+    /// check if the whole `s` matches
+    pub fn is_match(&self, s: &[u8]) -> bool {
+        self.match_len(s) == Some(s.len())
+    }
+
+    /// length of the longest prefix of `s`, that matches
+    pub fn match_len(&self, s: &[u8]) -> Option<usize> {
+        self.match_at(s, 0)
+    }
+
+    /// end of the longest match, that starts at s[start..]; anchors see bytes out of it
+    pub fn match_at(&self, s: &[u8], start: usize) -> Option<usize> {
+        if let Some(lazy) = &self.lazy {
+            return lazy.longest_at(s, start).map(|m| m.0);
+        }
+        if let Some(vm) = &self.pike {
+            return vm.match_at(s, start);
+        }
+        self.walk(s, start)
+            .iter()
+            .enumerate()
+            .rposition(|(i, n)| self.accept[*n][self.classes.look(s.get(start + i).copied())])
+            .map(|len| start + len)
+    }
+
+    /// spans of all groups for the longest matching prefix of `s`
+    pub fn captures(&self, s: &[u8]) -> Option<Vec<Span>> {
+        self.captures_at(s, 0)
+    }
+
+    /// spans of all groups for the longest match, that starts at s[start..]
+    /// among paths of the match, alternatives are preferred from left to right and repeats are greedy
+    pub fn captures_at(&self, s: &[u8], start: usize) -> Option<Vec<Span>> {
+        if let Some(vm) = &self.pike {
+            return vm.captures_at(s, start);
+        }
+        match self.tagged() {
+            Tagged::Dfa(tagged) => tagged.captures_at(s, start),
+            Tagged::Pike(vm) => vm.captures_at(s, start),
+        }
+    }
+
+    /// spans of all groups for the longest matching prefix of `s`, by posix rules
+    pub fn posix_captures(&self, s: &[u8]) -> Option<Vec<Span>> {
+        self.posix_captures_at(s, 0)
+    }
+
+    /// spans of all groups for the longest match, that starts at s[start..], by posix rules:
+    /// subexpressions are leftmost-longest, the last iteration of a repeat wins
+    pub fn posix_captures_at(&self, s: &[u8], start: usize) -> Option<Vec<Span>> {
+        let end = self.match_at(s, start)?;
+        Some(self.posix().captures(&self.classes, s, start, end))
+    }
+
+    /// index of the group, named with (?<name>...)
+    pub fn group(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    /// span of the named group for the longest matching prefix of `s`
+    pub fn capture(&self, s: &[u8], name: &str) -> Option<Span> {
+        let g = self.group(name)?;
+        self.captures(s)?.get(g).copied()
+    }
+
+    /// check if `groups` are consistent with the run over `s`
+    /// run starts at the beginning of group 0, all offsets are relative to `s`
+    /// without dfa, groups are compared with captures
+    pub fn check_captures(&self, s: &[u8], groups: &[Span]) -> bool {
+        let start = groups.first().and_then(|g| g.0).unwrap_or(0);
+        if self.pike.is_some() {
+            let res = self.captures_at(s, start);
+            return res.is_some_and(|g| g.len() >= groups.len() && g[..groups.len()] == *groups);
+        }
+        let mut state = self.start(s, start);
+        let mut failed = false;
+        for (i, c) in s
+            .iter()
+            .enumerate()
+            .skip(start)
+            .chain(std::iter::once((s.len(), &0)))
+        {
+            for (g, (h, t)) in groups.iter().enumerate() {
+                if (*h == Some(i) || *t == Some(i)) && failed {
+                    return false;
+                }
+                if *h == Some(i) && !self.head[state].contains(&g) {
+                    return false;
+                }
+                if *t == Some(i) && !self.tail[state].contains(&g) {
+                    return false;
+                }
+            }
+            if i != s.len() {
+                match next_state(&self.classes, &self.nodes, state, *c) {
+                    None => failed = true,
+                    Some(next) => state = next,
+                }
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test_matching {
+    use super::*;
+
+    fn regex(s: &[u8]) -> Regex {
+        let config = Config {
+            auto_groups: true,
+            ..Config::default()
+        };
+        compile(s, config).unwrap()
+    }
+
+    #[test]
+    fn is_match() {
+        let r = regex(b"(a(b|c)*d)");
+        assert!(r.is_match(b"ad"));
+        assert!(r.is_match(b"abcbd"));
+        assert!(!r.is_match(b"abc"));
+        assert!(!r.is_match(b"abdd"));
+        assert!(!r.is_match(b""));
+        assert_eq!(r.match_len(b"abdd"), Some(3));
+        assert_eq!(r.match_len(b"xad"), None);
+
+        let r = compile(b"a*b?", Config::default()).unwrap();
+        assert!(r.is_match(b""));
+        assert!(r.is_match(b"aab"));
+        assert!(!r.is_match(b"abb"));
+        assert_eq!(r.match_len(b"abb"), Some(2));
+        assert_eq!(r.groups(), 0);
+        assert_eq!(r.captures(b"b"), Some(vec![]));
+
+        let r = compile(b"\\xff[\\x80-\\xfe]+.[^a]", Config::default()).unwrap();
+        assert!(r.is_match(b"\xff\x80\xfe\x00\xc0"));
+        assert!(r.is_match(b"\xff\x80\xff\xff"));
+        assert!(!r.is_match(b"\xff\x7f\x00\xc0"));
+        assert!(!r.is_match(b"\xff\x80\x00a"));
+    }
+
+    #[test]
+    fn captures() {
+        let r = regex(b"((a)(b*))");
+        assert_eq!(r.groups(), 3);
+        assert_eq!(
+            r.captures(b"abbc"),
+            Some(vec![
+                (Some(0), Some(3)),
+                (Some(0), Some(1)),
+                (Some(1), Some(3))
+            ])
+        );
+        assert_eq!(r.captures(b"c"), None);
+
+        let r = regex(b"((a)|b)");
+        assert_eq!(
+            r.captures(b"b"),
+            Some(vec![(Some(0), Some(1)), (None, None)])
+        );
+    }
+
+    #[test]
+    fn check_captures() {
+        let r = regex(b"(hello)");
+        assert!(r.check_captures(b"hello", &[(Some(0), Some(5))]));
+        assert!(r.check_captures(b"xhellou", &[(Some(1), Some(6))]));
+        assert!(!r.check_captures(b"hello", &[(Some(0), Some(4))]));
+        assert!(!r.check_captures(b"hellx", &[(Some(0), Some(5))]));
+    }
+}
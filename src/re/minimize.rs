@@ -0,0 +1,143 @@
+/*
+ * Minimize DFA
+ *
+ * Moore algorithm:
+ * - initially, states are equal if they have the same group heads, tails and accepted rules
+ * - split classes until all transitions of equal states lead to equal states
+ * - start state remains 0, start states for other looks are renumbered
+ */
+
+use std::hash::Hash;
+
+/// number states by key, equal keys get equal ids, ids are given in order of first occurrence
+fn classify<T: Hash + Eq>(n: usize, key: impl Fn(usize) -> T) -> Vec<usize> {
+    let mut ids: HashMap<T, usize> = HashMap::new();
+    (0..n)
+        .map(|i| {
+            let id = ids.len();
+            *ids.entry(key(i)).or_insert(id)
+        })
+        .collect()
+}
+
+fn sorted(s: &HashSet<usize>) -> Vec<usize> {
+    let mut v: Vec<usize> = s.iter().copied().collect();
+    v.sort();
+    v
+}
+
+impl DFA {
+    pub fn minimize(self) -> DFA {
+        let n = self.nodes.len();
+        let mut class = classify(n, |i| {
+            (
+                sorted(&self.head[i]),
+                sorted(&self.tail[i]),
+                self.accept[i].iter().map(sorted).collect::<Vec<_>>(),
+            )
+        });
+        loop {
+            let next = classify(n, |i| {
+                let edges: Vec<usize> = self.nodes[i]
+                    .iter()
+                    .map(|s| if *s == DEAD { DEAD } else { class[*s] })
+                    .collect();
+                (class[i], edges)
+            });
+            if next.iter().max() == class.iter().max() {
+                break;
+            }
+            class = next;
+        }
+
+        let mut dfa = DFA {
+            classes: self.classes.clone(),
+            nodes: vec![],
+            start: self.start.iter().map(|s| class[*s]).collect(),
+            head: vec![],
+            tail: vec![],
+            accept: vec![],
+        };
+        for i in 0..n {
+            if class[i] != dfa.nodes.len() {
+                continue;
+            }
+            dfa.nodes.push(
+                self.nodes[i]
+                    .iter()
+                    .map(|s| if *s == DEAD { DEAD } else { class[*s] })
+                    .collect(),
+            );
+            dfa.head.push(self.head[i].clone());
+            dfa.tail.push(self.tail[i].clone());
+            dfa.accept.push(self.accept[i].clone());
+        }
+        dfa
+    }
+}
+
+#[cfg(test)]
+mod test_minimize {
+    use super::*;
+
+    fn dfa(s: &[u8]) -> DFA {
+        build_dfa(build_nfa(Lexer::new(s, Config::default())).unwrap())
+    }
+
+    #[test]
+    fn merge_states() {
+        let d = dfa(b"ab|cb");
+        assert_eq!(d.nodes.len(), 5);
+        let d = d.minimize();
+        assert_eq!(d.nodes.len(), 3);
+        assert_eq!(d.next(0, b'a'), Some(1));
+        assert_eq!(d.next(0, b'c'), Some(1));
+        assert_eq!(d.next(1, b'b'), Some(2));
+        assert_eq!(d.next(1, b'a'), None);
+        assert_eq!(d.accept[1], vec![HashSet::new(); LOOKS]);
+        assert_eq!(d.accept[2], vec![HashSet::from([0]); LOOKS]);
+
+        let d = dfa(b"(a|b)*abb").minimize();
+        assert_eq!(d.nodes.len(), 4);
+
+        let d = dfa(b"x(a{1,20})").minimize();
+        assert_eq!(d.nodes.len(), 22);
+    }
+
+    #[test]
+    fn keep_groups() {
+        let d = dfa(b"\\Aa\\Zb|cb").minimize();
+        assert_eq!(d.nodes.len(), 4);
+        assert_eq!(d.tail[d.next(0, b'a').unwrap()], HashSet::from([0]));
+        assert_eq!(d.tail[d.next(0, b'c').unwrap()], HashSet::new());
+        assert_eq!(d.head[0], HashSet::from([0]));
+
+        let d = dfa(b"\\Aa\\Zb|\\Ac\\Zb").minimize();
+        assert_eq!(d.nodes.len(), 4);
+        assert_eq!(d.tail[d.next(0, b'c').unwrap()], HashSet::from([1]));
+    }
+
+    #[test]
+    fn keep_rules() {
+        let rules = vec![
+            Lexer::new(b"ab", Config::default()),
+            Lexer::new(b"cb", Config::default()),
+        ];
+        let d = build_dfa(build_nfa_rules(rules).unwrap()).minimize();
+        assert_eq!(d.nodes.len(), 5);
+    }
+
+    #[test]
+    fn keep_starts() {
+        let d = dfa(b"^a|b").minimize();
+        assert_eq!(d.start[LOOK_EDGE], 0);
+        assert_eq!(d.start[LOOK_NEWLINE], d.start[LOOK_OTHER]);
+        assert_ne!(d.start[LOOK_OTHER], 0);
+        assert_eq!(d.next(d.start[LOOK_OTHER], b'a'), None);
+        assert_eq!(d.next(d.next(0, b'a').unwrap(), b'a'), None);
+
+        let d = dfa(b"a*").minimize();
+        assert_eq!(d.start, vec![0; LOOKS]);
+        assert_eq!(d.nodes.len(), 1);
+    }
+}
@@ -0,0 +1,189 @@
+/*
+ * Pike VM
+ *
+ * - simulate tagged nfa over the input: threads are configurations of tagged dfa, computed on the fly
+ * - each thread has registers for tags and for the start of its match
+ * - threads are ordered by the start of their match, then by priority, so earlier starts win a node;
+ *   a new thread, starting at the current offset, is added with the lowest priority, until a match is found
+ * - once a match is found, threads, that start after it, are dropped; the match is extended while threads remain
+ * - takes O(nodes) time for each byte, no states are cached
+ */
+
+pub struct PikeVM {
+    nfa: TaggedNFA,
+    classes: ByteClasses,
+    begin: usize,
+    /// number of tags, register `tags` holds the start of the match
+    tags: usize,
+}
+
+/// match of pike vm: (start, end, registers)
+type PikeMatch = (usize, usize, Vec<Option<usize>>);
+
+pub fn build_pike_vm(nfa: &NFAUncooked) -> PikeVM {
+    PikeVM {
+        nfa: TaggedNFA::new(nfa),
+        classes: nfa.classes(),
+        begin: nfa.begin,
+        tags: 2 * nfa.groups,
+    }
+}
+
+impl PikeVM {
+    /// leftmost-longest match, that starts at s[from..], or exactly at `from`, if `anchored`
+    fn run(&self, s: &[u8], from: usize, anchored: bool) -> Option<PikeMatch> {
+        let mut kernel: Vec<usize> = Vec::new();
+        let mut regs: Vec<Vec<Option<usize>>> = Vec::new();
+        let mut res: Option<PikeMatch> = None;
+        for pos in from..=s.len() {
+            if res.is_none() && (pos == from || !anchored) {
+                let mut r = vec![None; self.tags + 1];
+                r[self.tags] = Some(pos);
+                kernel.push(self.begin);
+                regs.push(r);
+            }
+            if kernel.is_empty() {
+                break;
+            }
+            let prev = self.classes.look(s[..pos].last().copied());
+            let closure = self
+                .nfa
+                .closure(&kernel, prev, self.classes.look(s.get(pos).copied()));
+            if let Some((_, op)) = closure.iter().find(|(n, _)| self.nfa.accept[*n]) {
+                let r = apply_tags(&regs, op, pos);
+                res = Some((r[self.tags].unwrap(), pos, r));
+            }
+            let Some(c) = s.get(pos) else {
+                break;
+            };
+            let last = res.as_ref().map_or(usize::MAX, |m| m.0);
+            let mut used = vec![false; self.nfa.edges.len()];
+            let mut ops: Vec<&TagOp> = Vec::new();
+            kernel.clear();
+            for (n, op) in closure.iter() {
+                if regs[op.0][self.tags].is_some_and(|start| start > last) {
+                    continue;
+                }
+                for (m, set) in self.nfa.edges[*n].iter() {
+                    if set.contains(*c) && !used[*m] {
+                        used[*m] = true;
+                        kernel.push(*m);
+                        ops.push(op);
+                    }
+                }
+            }
+            regs = ops
+                .into_iter()
+                .map(|op| apply_tags(&regs, op, pos))
+                .collect();
+        }
+        res
+    }
+
+    /// end of the longest match, that starts at s[start..]
+    pub fn match_at(&self, s: &[u8], start: usize) -> Option<usize> {
+        self.run(s, start, true).map(|m| m.1)
+    }
+
+    /// leftmost-longest match within s[from..]: (start, end)
+    pub fn find_at(&self, s: &[u8], from: usize) -> Option<(usize, usize)> {
+        self.run(s, from, false).map(|m| (m.0, m.1))
+    }
+
+    /// spans of all groups for the longest match, that starts at s[start..]
+    pub fn captures_at(&self, s: &[u8], start: usize) -> Option<Vec<Span>> {
+        self.run(s, start, true)
+            .map(|m| tag_spans(&m.2[..self.tags]))
+    }
+}
+
+#[cfg(test)]
+mod test_pike {
+    use super::*;
+
+    fn pike(s: &[u8]) -> PikeVM {
+        let config = Config {
+            auto_groups: true,
+            ..Config::default()
+        };
+        build_pike_vm(&nfa_uncooked(Lexer::new(s, config)).unwrap())
+    }
+
+    #[test]
+    fn just_works() {
+        let vm = pike(b"(a|ab)(c|bcd)?");
+        assert_eq!(vm.match_at(b"abcd", 0), Some(4));
+        assert_eq!(vm.match_at(b"xabcd", 0), None);
+        assert_eq!(
+            vm.captures_at(b"abcd", 0),
+            Some(vec![(Some(0), Some(1)), (Some(1), Some(4))])
+        );
+        assert_eq!(vm.find_at(b"xxabcd", 0), Some((2, 6)));
+        assert_eq!(vm.find_at(b"xxabcdab", 3), Some((6, 8)));
+        assert_eq!(vm.find_at(b"xx", 0), None);
+    }
+
+    #[test]
+    fn leftmost_longest() {
+        let vm = pike(b"a+b|ab*|x*");
+        assert_eq!(vm.find_at(b"caaab", 0), Some((0, 0)));
+        assert_eq!(vm.find_at(b"caaab", 1), Some((1, 5)));
+        let vm = pike(b"aaaa|b|ab");
+        assert_eq!(vm.find_at(b"aaabaaaa", 0), Some((2, 4)));
+        let vm = pike(b"\\bab+\\b");
+        assert_eq!(vm.find_at(b"abc abbb", 0), Some((4, 8)));
+    }
+
+    #[test]
+    fn same_as_tdfa() {
+        for (re, s) in [
+            (&b"(a*)(a*)"[..], &b"aaa"[..]),
+            (b"((a)|b)+", b"aab"),
+            (b"(a{2})(a{1,2})(a+)", b"aaaaa"),
+            (b"(a$|a)(b?)", b"a"),
+        ] {
+            let config = Config {
+                auto_groups: true,
+                ..Config::default()
+            };
+            let nfa = nfa_uncooked(Lexer::new(re, config)).unwrap();
+            assert_eq!(
+                build_pike_vm(&nfa).captures_at(s, 0),
+                build_tdfa(&nfa).captures_at(s, 0)
+            );
+        }
+    }
+
+    #[test]
+    fn engines() {
+        let re = b"(a|b)*a(a|b){8}";
+        let config = |engine, dfa_states| Config {
+            engine,
+            dfa_states,
+            ..Config::default()
+        };
+        let auto = compile(re, config(Engine::Auto, 100)).unwrap();
+        let dfa = compile(re, config(Engine::DFA, 100)).unwrap();
+        assert!(auto.pike.is_some() && auto.lazy.is_some() && auto.nodes.is_empty());
+        assert!(dfa.pike.is_none() && dfa.nodes.len() > 100);
+        assert!(compile(re, Config::default()).unwrap().pike.is_none());
+        let s = b"bbabbbbbbbbabaaaaaaaaab";
+        assert_eq!(auto.match_len(s), dfa.match_len(s));
+        assert_eq!(auto.captures(s), dfa.captures(s));
+        assert_eq!(
+            auto.find_iter(s).collect::<Vec<_>>(),
+            dfa.find_iter(s).collect::<Vec<_>>()
+        );
+
+        let vm = compile(b"\\A[a-z]+\\Z|\\A[0-9]+\\Z", config(Engine::PikeVM, 100)).unwrap();
+        assert!(vm.pike.is_some() && vm.lazy.is_none());
+        assert_eq!(vm.groups(), 2);
+        assert!(vm.is_match(b"abc"));
+        assert_eq!(
+            vm.find_iter(b"ab 12").collect::<Vec<_>>(),
+            vec![(0, 2), (3, 5)]
+        );
+        assert!(vm.check_captures(b"ab 12", &[(Some(0), Some(2))]));
+        assert!(!vm.check_captures(b"ab 12", &[(Some(0), Some(1))]));
+    }
+}
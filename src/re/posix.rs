@@ -0,0 +1,389 @@
+/*
+ * POSIX submatches
+ *
+ * - ast of the pattern is converted into a tree of terms, match is found by dfa, then it is split between terms
+ * - ends(t, i) are offsets, where term t, started at offset i, may end; they are memoized as ranges and
+ *   computed with an explicit stack, so long inputs need no deep recursion
+ * - concatenation gives the longest span to its first term, that still lets the rest match,
+ *   then continues with the rest: subexpressions are leftmost-longest in order of their start
+ * - alternation takes the first alternative, that matches the span
+ * - each iteration of a repeat is the longest one, groups are taken from the last iteration,
+ *   groups within the repeat, that do not participate in the last iteration, are unset
+ * - a repeat, that matches an empty span, still makes an empty iteration, if it can
+ */
+
+use std::rc::Rc;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Char(Charset),
+    Assert(Assert),
+    Concat(Vec<usize>),
+    Alt(Vec<usize>),
+    Repeat(usize, usize, Option<usize>),
+    Group(usize, usize),
+}
+
+/// pattern as a tree of terms, nodes are indices within `terms`
+#[derive(Debug, Clone)]
+pub struct Posix {
+    terms: Vec<Term>,
+    root: usize,
+    groups: usize,
+}
+
+struct PosixRun<'a> {
+    posix: &'a Posix,
+    classes: &'a ByteClasses,
+    s: &'a [u8],
+    /// ends for (term, index within concatenation or number of iterations done, offset)
+    ends: HashMap<Key, Rc<Ends>>,
+    groups: Vec<Span>,
+}
+
+/// (term, index within concatenation or number of iterations done, offset)
+type Key = (usize, usize, usize);
+
+/// set of offsets as sorted disjoint inclusive ranges, so that ends of repeats take little memory
+#[derive(Debug, Default)]
+struct Ends(Vec<(usize, usize)>);
+
+impl Ends {
+    fn union<'a>(sets: impl Iterator<Item = &'a Ends>, point: Option<usize>) -> Ends {
+        let mut ranges: Vec<(usize, usize)> = sets.flat_map(|e| e.0.iter().copied()).collect();
+        ranges.extend(point.map(|i| (i, i)));
+        ranges.sort_unstable();
+        let mut res: Vec<(usize, usize)> = Vec::new();
+        for (a, b) in ranges {
+            match res.last_mut() {
+                Some(last) if a <= last.1 + 1 => last.1 = last.1.max(b),
+                _ => res.push((a, b)),
+            }
+        }
+        Ends(res)
+    }
+
+    fn contains(&self, i: usize) -> bool {
+        use std::cmp::Ordering;
+        let range = |(a, b): &(usize, usize)| match (*b < i, *a > i) {
+            (true, _) => Ordering::Less,
+            (_, true) => Ordering::Greater,
+            _ => Ordering::Equal,
+        };
+        self.0.binary_search_by(range).is_ok()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().flat_map(|(a, b)| *a..=*b)
+    }
+
+    /// offsets within i..=j from the last one
+    fn rev_within(&self, i: usize, j: usize) -> impl Iterator<Item = usize> + '_ {
+        self.0
+            .iter()
+            .rev()
+            .flat_map(move |(a, b)| ((*a).max(i)..=(*b).min(j)).rev())
+    }
+}
+
+pub fn build_posix(ast: &Ast, groups: usize) -> Posix {
+    let mut posix = Posix {
+        terms: vec![],
+        root: 0,
+        groups,
+    };
+    let alts = match ast {
+        Ast::Alt(v) => v.iter().collect(),
+        t => vec![t],
+    };
+    // each alternative of the pattern is a concatenation
+    let alts = alts
+        .into_iter()
+        .map(|t| {
+            let seq = match t {
+                Ast::Concat(v) => v.iter().map(|t| posix.term_of(t)).collect(),
+                t => vec![posix.term_of(t)],
+            };
+            posix.term(Term::Concat(seq))
+        })
+        .collect();
+    posix.root = posix.term(Term::Alt(alts));
+    posix
+}
+
+impl Posix {
+    fn term(&mut self, t: Term) -> usize {
+        self.terms.push(t);
+        self.terms.len() - 1
+    }
+
+    fn term_of(&mut self, ast: &Ast) -> usize {
+        let t = match ast {
+            Ast::Char(charset) => Term::Char(charset.clone()),
+            Ast::Assert(x) => Term::Assert(*x),
+            Ast::Unicode(set) => {
+                let mut alt = Vec::new();
+                for bytes in set.utf8_sequences() {
+                    let chars = bytes
+                        .iter()
+                        .map(|(lo, hi)| self.term(Term::Char(charset!([*lo, *hi]))))
+                        .collect();
+                    alt.push(self.term(Term::Concat(chars)));
+                }
+                Term::Alt(alt)
+            }
+            Ast::Concat(v) => Term::Concat(v.iter().map(|t| self.term_of(t)).collect()),
+            Ast::Alt(v) => Term::Alt(v.iter().map(|t| self.term_of(t)).collect()),
+            Ast::Repeat(t, min, max) => {
+                let t = self.term_of(t);
+                Term::Repeat(t, *min as usize, max.map(|m| m as usize))
+            }
+            Ast::Group(g, _, t) => {
+                let t = self.term_of(t);
+                Term::Group(*g, t)
+            }
+        };
+        self.term(t)
+    }
+
+    /// groups within the term
+    fn groups_within(&self, t: usize, res: &mut Vec<usize>) {
+        match &self.terms[t] {
+            Term::Char(_) | Term::Assert(_) => (),
+            Term::Concat(v) | Term::Alt(v) => v.iter().for_each(|t| self.groups_within(*t, res)),
+            Term::Repeat(t, _, _) => self.groups_within(*t, res),
+            Term::Group(g, t) => {
+                res.push(*g);
+                self.groups_within(*t, res);
+            }
+        }
+    }
+
+    /// spans of all groups for the match s[start..end]
+    pub fn captures(&self, classes: &ByteClasses, s: &[u8], start: usize, end: usize) -> Vec<Span> {
+        let mut run = PosixRun {
+            posix: self,
+            classes,
+            s,
+            ends: HashMap::new(),
+            groups: vec![(None, None); self.groups],
+        };
+        run.split(self.root, start, end);
+        run.groups
+    }
+}
+
+impl PosixRun<'_> {
+    /// number of iterations after one more iteration of a repeat, unbounded repeats count up to min + 1
+    fn iteration(&self, t: usize, k: usize) -> usize {
+        match self.posix.terms[t] {
+            Term::Repeat(_, min, None) => (min + 1).min(k + 1),
+            _ => k + 1,
+        }
+    }
+
+    /// ends of the term, evaluated with an explicit stack: dependencies are at the same or later offsets
+    fn ends(&mut self, t: usize, k: usize, i: usize) -> Rc<Ends> {
+        let mut stack = vec![(t, k, i)];
+        while let Some(&key) = stack.last() {
+            if self.ends.contains_key(&key) {
+                stack.pop();
+                continue;
+            }
+            match self.try_ends(key) {
+                Ok(res) => {
+                    self.ends.insert(key, Rc::new(res));
+                    stack.pop();
+                }
+                Err(missing) => stack.extend(missing),
+            }
+        }
+        self.ends[&(t, k, i)].clone()
+    }
+
+    /// ends of the term, or the ends it depends on, that are not computed yet
+    fn try_ends(&self, (t, k, i): Key) -> std::result::Result<Ends, Vec<Key>> {
+        let known = |key: Key| self.ends.get(&key).ok_or_else(|| vec![key]);
+        match &self.posix.terms[t] {
+            Term::Char(set) => {
+                let end = self
+                    .s
+                    .get(i)
+                    .is_some_and(|c| set.contains(*c))
+                    .then_some(i + 1);
+                Ok(Ends::union(std::iter::empty(), end))
+            }
+            Term::Assert(x) => {
+                let prev = self.classes.look(self.s[..i].last().copied());
+                let end = x
+                    .check(prev, self.classes.look(self.s.get(i).copied()))
+                    .then_some(i);
+                Ok(Ends::union(std::iter::empty(), end))
+            }
+            Term::Concat(v) => match v.get(k) {
+                None => Ok(Ends::union(std::iter::empty(), Some(i))),
+                Some(first) => {
+                    let first = known((*first, 0, i))?;
+                    self.union_of(first.iter().map(|m| (t, k + 1, m)), None)
+                }
+            },
+            Term::Alt(v) => self.union_of(v.iter().map(|a| (*a, 0, i)), None),
+            Term::Group(_, inner) => self.union_of([(*inner, 0, i)].into_iter(), None),
+            Term::Repeat(inner, min, max) => {
+                let empty = (k >= *min).then_some(i);
+                if *max == Some(k) {
+                    return Ok(Ends::union(std::iter::empty(), empty));
+                }
+                let next = self.iteration(t, k);
+                let first = known((*inner, 0, i))?;
+                let rest = first
+                    .iter()
+                    .filter(|m| *m != i || k < *min)
+                    .map(|m| (t, next, m));
+                self.union_of(rest, empty)
+            }
+        }
+    }
+
+    fn union_of(
+        &self,
+        keys: impl Iterator<Item = Key>,
+        point: Option<usize>,
+    ) -> std::result::Result<Ends, Vec<Key>> {
+        let keys: Vec<Key> = keys.collect();
+        let missing: Vec<Key> = keys
+            .iter()
+            .filter(|key| !self.ends.contains_key(key))
+            .copied()
+            .collect();
+        match missing.is_empty() {
+            true => Ok(Ends::union(keys.iter().map(|key| &*self.ends[key]), point)),
+            false => Err(missing),
+        }
+    }
+
+    /// longest span for the first part s[i..m], so that the rest matches s[m..j]
+    fn longest(&mut self, first: usize, i: usize, rest: (usize, usize), j: usize) -> Option<usize> {
+        let ends = self.ends(first, 0, i);
+        let m = ends
+            .rev_within(i, j)
+            .find(|m| self.ends(rest.0, rest.1, *m).contains(j));
+        m
+    }
+
+    /// split the match s[i..j] of term t between its subterms, set groups;
+    /// parts are split in order of their start with an explicit stack
+    fn split(&mut self, t: usize, i: usize, j: usize) {
+        let posix = self.posix;
+        let mut stack = vec![(t, 0, i, j)];
+        while let Some((t, k, i, j)) = stack.pop() {
+            match &posix.terms[t] {
+                Term::Char(_) | Term::Assert(_) => (),
+                Term::Group(g, inner) => {
+                    self.groups[*g] = (Some(i), Some(j));
+                    stack.push((*inner, 0, i, j));
+                }
+                Term::Alt(v) => {
+                    if let Some(a) = v.iter().find(|a| self.ends(**a, 0, i).contains(j)) {
+                        stack.push((*a, 0, i, j));
+                    }
+                }
+                Term::Concat(v) => {
+                    if let Some(first) = v.get(k) {
+                        if let Some(m) = self.longest(*first, i, (t, k + 1), j) {
+                            stack.push((t, k + 1, m, j));
+                            stack.push((*first, 0, i, m));
+                        }
+                    }
+                }
+                Term::Repeat(inner, min, max) => {
+                    let empty = i == j && k == 0 && self.ends(*inner, 0, i).contains(i);
+                    if (k >= *min && i == j && !empty) || *max == Some(k) {
+                        continue;
+                    }
+                    let next = self.iteration(t, k);
+                    if let Some(m) = self.longest(*inner, i, (t, next), j) {
+                        let mut groups = Vec::new();
+                        posix.groups_within(*inner, &mut groups);
+                        for g in groups {
+                            self.groups[g] = (None, None);
+                        }
+                        if m != i {
+                            stack.push((t, next, m, j));
+                        }
+                        stack.push((*inner, 0, i, m));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_posix {
+    use super::*;
+
+    fn captures(re: &[u8], s: &[u8]) -> Option<Vec<Span>> {
+        let config = Config {
+            auto_groups: true,
+            ..Config::default()
+        };
+        compile(re, config).unwrap().posix_captures(s)
+    }
+
+    #[test]
+    fn parse() {
+        let ast = build_ast(Lexer::new(b"\\Aa\\Z|b*", Config::default())).unwrap();
+        let posix = build_posix(&ast, 1);
+        assert_eq!(posix.groups, 1);
+        assert_eq!(posix.terms[posix.root], Term::Alt(vec![2, 5]));
+        assert_eq!(posix.terms[1], Term::Group(0, 0));
+        assert_eq!(posix.terms[4], Term::Repeat(3, 0, None));
+    }
+
+    #[test]
+    fn leftmost_longest() {
+        assert_eq!(
+            captures(b"(a|ab)(c|bcd)(d*)", b"abcd"),
+            Some(vec![
+                (Some(0), Some(2)),
+                (Some(2), Some(3)),
+                (Some(3), Some(4))
+            ])
+        );
+        assert_eq!(
+            captures(b"(a*)(ab)*(b*)", b"abb"),
+            Some(vec![(Some(0), Some(1)), (None, None), (Some(1), Some(3))])
+        );
+        assert_eq!(
+            captures(b"(a|ab)(bc|c)", b"abc"),
+            Some(vec![(Some(0), Some(2)), (Some(2), Some(3))])
+        );
+        assert_eq!(captures(b"(a)b", b"ac"), None);
+    }
+
+    #[test]
+    fn repeats() {
+        assert_eq!(
+            captures(b"(a|(b))*", b"ba"),
+            Some(vec![(Some(1), Some(2)), (None, None)])
+        );
+        assert_eq!(captures(b"(a*)*", b"b"), Some(vec![(Some(0), Some(0))]));
+        assert_eq!(captures(b"(a*)+", b"aab"), Some(vec![(Some(0), Some(2))]));
+        assert_eq!(captures(b"(a|b)*", b""), Some(vec![(None, None)]));
+        assert_eq!(
+            captures(b"(a){2}(a)?", b"aa"),
+            Some(vec![(Some(1), Some(2)), (None, None)])
+        );
+    }
+
+    #[test]
+    fn long_input() {
+        let mut s = vec![b'a'; 20000];
+        s.push(b'c');
+        assert_eq!(
+            captures(b"(a|b)*c", &s),
+            Some(vec![(Some(19999), Some(20000))])
+        );
+    }
+}
@@ -0,0 +1,185 @@
+/*
+ * Search compiled regex
+ *
+ * - run reversed unanchored dfa from the end of input: it accepts at every offset, where a match starts
+ *   (anchors are reversed too: its look behind is the byte after the offset); it is built on the first search
+ *   and is lazy, if it gets too large
+ * - the leftmost such offset starts the match, it is extended with the longest anchored match
+ * - empty match right after the previous match is skipped, after an empty match the search resumes
+ *   at the next byte, or at the next char in utf8 mode
+ */
+
+pub struct Matches<'a> {
+    regex: &'a Regex,
+    s: &'a [u8],
+    starts: Vec<bool>,
+    pos: usize,
+    last: Option<usize>,
+}
+
+impl Regex {
+    /// starts[i] is true, if some match starts at s[i..]
+    fn starts(&self, s: &[u8]) -> Vec<bool> {
+        let reverse = match self.reverse() {
+            Reverse::Dfa(dfa) => dfa,
+            Reverse::Lazy(lazy) => return lazy.starts(s),
+        };
+        let accepts = |n: usize, i: usize| {
+            let look = reverse.classes.look(s[..i].last().copied());
+            !reverse.accept[n][look].is_empty()
+        };
+        let mut starts = vec![false; s.len() + 1];
+        let mut state = reverse.start[LOOK_EDGE];
+        starts[s.len()] = accepts(state, s.len());
+        for i in (0..s.len()).rev() {
+            match reverse.next(state, s[i]) {
+                Some(n) => state = n,
+                None => break,
+            }
+            starts[i] = accepts(state, i);
+        }
+        starts
+    }
+
+    /// leftmost-longest match within `s`: (start, end)
+    pub fn find(&self, s: &[u8]) -> Option<(usize, usize)> {
+        self.find_iter(s).next()
+    }
+
+    /// all non-overlapping matches within `s`, from left to right
+    pub fn find_iter<'a>(&'a self, s: &'a [u8]) -> Matches<'a> {
+        Matches {
+            regex: self,
+            s,
+            starts: match (&self.pike, &self.lazy) {
+                (Some(_), None) => vec![],
+                _ => self.starts(s),
+            },
+            pos: 0,
+            last: None,
+        }
+    }
+}
+
+impl Matches<'_> {
+    /// offset of the char after the one at `pos`
+    fn next_char(&self, pos: usize) -> usize {
+        let mut next = pos + 1;
+        if self.regex.utf8 {
+            while self.s.get(next).is_some_and(|c| c & 0xc0 == 0x80) {
+                next += 1;
+            }
+        }
+        next
+    }
+}
+
+impl Iterator for Matches<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        loop {
+            let (start, end) = match (&self.regex.pike, &self.regex.lazy) {
+                (Some(vm), None) => vm.find_at(self.s, self.pos)?,
+                _ => {
+                    let start = self.pos + self.starts.get(self.pos..)?.iter().position(|s| *s)?;
+                    (start, self.regex.match_at(self.s, start)?)
+                }
+            };
+            self.pos = if start == end {
+                self.next_char(start)
+            } else {
+                end
+            };
+            if start == end && self.last == Some(start) {
+                continue;
+            }
+            self.last = Some(end);
+            return Some((start, end));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_search {
+    use super::*;
+
+    fn regex(s: &[u8]) -> Regex {
+        compile(s, Config::default()).unwrap()
+    }
+
+    #[test]
+    fn find() {
+        let r = regex(b"a.c.|b");
+        assert_eq!(r.find(b"xabcd"), Some((1, 5)));
+        assert_eq!(r.find(b"xbbcd"), Some((1, 2)));
+        assert_eq!(r.find(b"xxx"), None);
+        assert_eq!(r.find(b""), None);
+
+        let r = regex(b"a+|ab");
+        assert_eq!(r.find(b"baaab"), Some((1, 4)));
+        assert_eq!(r.find(b"bab"), Some((1, 3)));
+
+        let r = regex(b"x*");
+        assert_eq!(r.find(b"abc"), Some((0, 0)));
+        assert_eq!(r.find(b""), Some((0, 0)));
+    }
+
+    #[test]
+    fn find_iter() {
+        let r = regex(b"\\d+");
+        let m: Vec<_> = r.find_iter(b"a1b22c333").collect();
+        assert_eq!(m, vec![(1, 2), (3, 5), (6, 9)]);
+
+        let r = regex(b"a*");
+        let m: Vec<_> = r.find_iter(b"baac").collect();
+        assert_eq!(m, vec![(0, 0), (1, 3), (4, 4)]);
+
+        let r = regex(b"ab|ba");
+        let m: Vec<_> = r.find_iter(b"abababa").collect();
+        assert_eq!(m, vec![(0, 2), (2, 4), (4, 6)]);
+    }
+
+    #[test]
+    fn anchors() {
+        let r = regex(b"^a|b$");
+        assert_eq!(r.find(b"ab"), Some((0, 1)));
+        assert_eq!(r.find(b"xab"), Some((2, 3)));
+        assert_eq!(r.find(b"xabx"), None);
+        let m: Vec<_> = r.find_iter(b"ab").collect();
+        assert_eq!(m, vec![(0, 1), (1, 2)]);
+
+        let config = Config {
+            multiline: true,
+            ..Config::default()
+        };
+        let r = compile(b"^\\w+$", config).unwrap();
+        let m: Vec<_> = r.find_iter(b"ab\ncd e\nf").collect();
+        assert_eq!(m, vec![(0, 2), (8, 9)]);
+
+        let r = regex(b"$");
+        assert_eq!(r.find(b"abc"), Some((3, 3)));
+
+        let r = regex(b"\\b\\w+\\b");
+        let m: Vec<_> = r.find_iter(b"ab, cd-e").collect();
+        assert_eq!(m, vec![(0, 2), (4, 6), (7, 8)]);
+        let r = regex(b"\\Bb\\B");
+        assert_eq!(r.find(b"b abc"), Some((3, 4)));
+    }
+
+    #[test]
+    fn utf8() {
+        let config = Config {
+            utf8: true,
+            ..Config::default()
+        };
+        let r = compile(b"x*", config.clone()).unwrap();
+        let m: Vec<_> = r.find_iter("é".as_bytes()).collect();
+        assert_eq!(m, vec![(0, 0), (2, 2)]);
+        let r = compile(b"a*", config).unwrap();
+        let m: Vec<_> = r.find_iter("aéb".as_bytes()).collect();
+        assert_eq!(m, vec![(0, 1), (3, 3), (4, 4)]);
+        let r = regex(b"x*");
+        assert_eq!(r.find_iter("é".as_bytes()).count(), 3);
+    }
+}
@@ -0,0 +1,321 @@
+/*
+ * Regex AST simplifier
+ *
+ * - rewrites the tree bottom-up into a smaller one, that matches the same strings with the same groups
+ * - items with groups are never merged, factored, dropped or collapsed (only their own items are rewritten),
+ *   nested concatenations and alternations are flattened, so group numbers and names stay
+ * - rewrites keep priorities of alternatives (the order, in which the subtree tries match lengths),
+ *   so that captures of outer groups do not change:
+ *   - nested concatenations and alternations are flattened
+ *   - an alternative is dropped, if it repeats an earlier one: `a|b|a` is `a|b`
+ *   - adjacent alternatives of single chars are merged into a charset: `a|b|[0-9]` is `[0-9ab]`
+ *   - adjacent alternatives with the same first char or assertion are factored: `abc|abd` is `ab[cd]`,
+ *     an empty rest is only allowed as the last one and becomes `?`: `abc|ab` is `abc?`
+ *   - nested `?`, `*` and `+` are collapsed: `(x?)*` is `x*`, `(x+)+` is `x+`, `(x{2}){3}` is `x{6}`
+ *   - adjacent repeats of the same item are joined: `a*a*` is `a*`, `a?a{2}` is `a{2,3}`
+ *   - merged repeat counts stay within 255, so that the tree prints back into a pattern: `a{255}a` is kept
+ */
+
+impl Ast {
+    /// smaller tree, that matches the same strings with the same groups
+    pub fn simplify(&self) -> Ast {
+        match self {
+            Ast::Concat(v) => simplify_concat(v.iter().map(|t| t.simplify()).collect()),
+            Ast::Alt(v) => simplify_alt(v.iter().map(|t| t.simplify()).collect()),
+            Ast::Repeat(t, min, max) => simplify_repeat(t.simplify(), *min, *max),
+            Ast::Group(g, name, t) => Ast::Group(*g, name.clone(), Box::new(t.simplify())),
+            t => t.clone(),
+        }
+    }
+
+    fn has_groups(&self) -> bool {
+        match self {
+            Ast::Group(_, _, _) => true,
+            Ast::Char(_) | Ast::Unicode(_) | Ast::Assert(_) => false,
+            Ast::Concat(v) | Ast::Alt(v) => v.iter().any(|t| t.has_groups()),
+            Ast::Repeat(t, _, _) => t.has_groups(),
+        }
+    }
+
+    /// first item of a concatenation, other trees are a single item
+    fn first(&self) -> &Ast {
+        match self {
+            Ast::Concat(v) => &v[0],
+            t => t,
+        }
+    }
+
+    /// items of a concatenation, other trees are a single item
+    fn items(self) -> Vec<Ast> {
+        match self {
+            Ast::Concat(v) => v,
+            t => vec![t],
+        }
+    }
+}
+
+/// repeat count of a merged repeat, if Display can print it back (see Error::Overflow)
+fn count(n: Option<u32>) -> Option<u32> {
+    n.filter(|n| *n <= 255)
+}
+
+fn simplify_concat(v: Vec<Ast>) -> Ast {
+    let mut res: Vec<Ast> = Vec::new();
+    for t in v.into_iter().flat_map(|t| t.items()) {
+        match (res.pop(), t) {
+            (Some(Ast::Repeat(a, min1, max1)), Ast::Repeat(b, min2, max2))
+                if a == b && !a.has_groups() =>
+            {
+                let max = max1.zip(max2).map(|(m1, m2)| count(m1.checked_add(m2)));
+                match (count(min1.checked_add(min2)), max) {
+                    (Some(min), None | Some(Some(_))) => {
+                        res.push(simplify_repeat(*a, min, max.flatten()))
+                    }
+                    _ => res.extend([Ast::Repeat(a, min1, max1), Ast::Repeat(b, min2, max2)]),
+                }
+            }
+            (last, t) => res.extend(last.into_iter().chain([t])),
+        }
+    }
+    Ast::concat(res)
+}
+
+fn simplify_alt(v: Vec<Ast>) -> Ast {
+    let mut alts: Vec<Ast> = Vec::new();
+    for t in v.into_iter().flat_map(|t| match t {
+        Ast::Alt(v) => v,
+        t => vec![t],
+    }) {
+        if !t.has_groups() && alts.contains(&t) {
+            continue;
+        }
+        alts.push(t);
+    }
+    let mut res: Vec<Ast> = Vec::new();
+    let mut alts = alts.into_iter().peekable();
+    while let Some(t) = alts.next() {
+        let first = t.first().clone();
+        if t.has_groups() || !matches!(first, Ast::Char(_) | Ast::Unicode(_) | Ast::Assert(_)) {
+            res.push(t);
+            continue;
+        }
+        // adjacent alternatives with the same first item, till the one, that is the item itself
+        let mut run = vec![t];
+        while run.last().unwrap() != &first
+            && alts
+                .peek()
+                .is_some_and(|t| t.first() == &first && !t.has_groups())
+        {
+            run.push(alts.next().unwrap());
+        }
+        if run.len() == 1 {
+            merge_chars(&mut res, run.pop().unwrap());
+            continue;
+        }
+        let optional = run.last().unwrap() == &first;
+        let rest: Vec<Ast> = run
+            .into_iter()
+            .filter_map(|t| {
+                let v = t.items();
+                (v.len() > 1).then(|| Ast::concat(v[1..].to_vec()))
+            })
+            .collect();
+        let rest = match optional {
+            true => simplify_repeat(simplify_alt(rest), 0, Some(1)),
+            false => simplify_alt(rest),
+        };
+        res.push(simplify_concat(vec![first, rest]));
+    }
+    Ast::alt(res)
+}
+
+/// append single char alternative, merging it with the previous one
+fn merge_chars(res: &mut Vec<Ast>, t: Ast) {
+    let ascii = |c: &Charset| c.iter().all(|b| b < 0x80);
+    let merged = match (res.last(), &t) {
+        (Some(Ast::Char(a)), Ast::Char(b)) => {
+            let mut c = a.clone();
+            c.add(b);
+            Ast::Char(c)
+        }
+        (Some(Ast::Unicode(a)), Ast::Unicode(b)) => {
+            let mut s = a.clone();
+            s.add(b);
+            Ast::Unicode(s)
+        }
+        (Some(Ast::Char(c)), Ast::Unicode(s)) | (Some(Ast::Unicode(s)), Ast::Char(c))
+            if ascii(c) =>
+        {
+            let mut s = s.clone();
+            s.add(&UnicodeSet::from_charset(c, true));
+            Ast::Unicode(s)
+        }
+        _ => {
+            res.push(t);
+            return;
+        }
+    };
+    *res.last_mut().unwrap() = merged;
+}
+
+fn simplify_repeat(t: Ast, min: u32, max: Option<u32>) -> Ast {
+    if t.has_groups() {
+        return Ast::Repeat(Box::new(t), min, max);
+    }
+    // ?, * and +
+    let simple = |min: u32, max: Option<u32>| min <= 1 && max.is_none_or(|m| m == 1 && min == 0);
+    let (t, min, max) = match t {
+        Ast::Repeat(t, n, Some(m)) if n == m && Some(min) == max => match count(n.checked_mul(min))
+        {
+            Some(k) => (*t, k, Some(k)),
+            None => (Ast::Repeat(t, n, Some(m)), min, max),
+        },
+        Ast::Repeat(t, n, m) if simple(n, m) && simple(min, max) => {
+            let max = if m.is_some() && max.is_some() {
+                Some(1)
+            } else {
+                None
+            };
+            (*t, n * min, max)
+        }
+        t => (t, min, max),
+    };
+    match (min, max) {
+        (1, Some(1)) => t,
+        _ => Ast::Repeat(Box::new(t), min, max),
+    }
+}
+
+#[cfg(test)]
+mod test_simplify {
+    use super::*;
+
+    fn simplified(s: &str) -> String {
+        build_ast(Lexer::new(s.as_bytes(), Config::default()))
+            .unwrap()
+            .simplify()
+            .to_string()
+    }
+
+    #[test]
+    fn rewrites() {
+        assert_eq!(simplified("(a|a)"), "a");
+        assert_eq!(simplified("a|b|c|[0-9]|a"), "[0-9a-c]");
+        assert_eq!(simplified("a*a*"), "a*");
+        assert_eq!(simplified("a?a{2}b+b*"), "a{2,3}b+");
+        assert_eq!(simplified("(x?)*|(y+)+|(z?)?|((w{2}){3})"), "x*|y+|z?|w{6}");
+        assert_eq!(simplified("abc|abd|abe"), "ab[c-e]");
+        assert_eq!(simplified("abc|ab"), "abc?");
+        assert_eq!(simplified("(ab|abc)d"), "a(b|bc)d");
+        assert_eq!(simplified("ab|x|ac"), "ab|x|ac");
+        assert_eq!(simplified("(x{2}){1,3}"), "x{2}{1,3}");
+        assert_eq!(simplified("a{200}a{55}"), "a{255}");
+        assert_eq!(simplified("a{255}a{255}"), "a{255}a{255}");
+        assert_eq!(simplified("a{2,200}a{2,56}"), "a{2,200}a{2,56}");
+        assert_eq!(simplified("(x{200}){2}"), "x{200}{2}");
+    }
+
+    #[test]
+    fn groups() {
+        let config = Config {
+            auto_groups: true,
+            ..Config::default()
+        };
+        let ast = |s: &str| build_ast(Lexer::new(s.as_bytes(), config.clone())).unwrap();
+        assert_eq!(ast("(a|a)b*b*").simplify().to_string(), "\\A(a)\\Zb*");
+        assert_eq!(ast("(a)*(a)*").simplify(), ast("(a)*(a)*"));
+        assert_eq!(
+            ast("(?<x>ab)|(?<y>ac)").simplify(),
+            ast("(?<x>ab)|(?<y>ac)")
+        );
+        assert_eq!(
+            ast("a(b)|a(c)|ad|ae").simplify().to_string(),
+            "a\\A(b)\\Z|a\\A(c)\\Z|a[de]"
+        );
+
+        // groups keep their numbers with Config::simplify
+        for s in ["\\A(a)b\\Z", "(x|x)(\\A(y*y*)z\\Z)"] {
+            let simple = Config {
+                simplify: true,
+                ..config.clone()
+            };
+            let (r1, r2) = (
+                compile(s.as_bytes(), config.clone()).unwrap(),
+                compile(s.as_bytes(), simple).unwrap(),
+            );
+            for input in [&b"ab"[..], b"xyyz", b"xz"] {
+                assert_eq!(r1.captures(input), r2.captures(input), "{s}");
+            }
+        }
+    }
+
+    /// patterns of the AT&T suite, compiled with and without Config::simplify
+    #[test]
+    fn att_patterns() {
+        let patterns = [
+            "(a|ab)(c|bcd)(d*)",
+            "(a|ab)(bcd|c)(d*)",
+            "((a)|b)*",
+            "(a+|b)*",
+            "(a*)*",
+            "(a*)+(b)",
+            "((a*)(b|abc))(c*)",
+            "(a|b)*c|(a|ab)*c",
+            "(.a|.b).*|.*(.a|.b)",
+            "(ab|a)(bc|c)",
+            "(aba|a*b)(aba|a*b)",
+            "x(ab|a)y|x(a|b)z",
+        ];
+        let inputs: [&[u8]; 8] = [
+            b"abcd", b"abbbcd", b"ababa", b"aabcc", b"abc", b"ababab", b"xabz", b"xay",
+        ];
+        let config = Config {
+            auto_groups: true,
+            ..Config::default()
+        };
+        let simple = Config {
+            simplify: true,
+            ..config.clone()
+        };
+        for s in patterns {
+            let (r1, r2) = (
+                compile(s.as_bytes(), config.clone()).unwrap(),
+                compile(s.as_bytes(), simple.clone()).unwrap(),
+            );
+            for input in inputs {
+                assert_eq!(r1.find(input), r2.find(input), "{s}");
+                assert_eq!(r1.captures(input), r2.captures(input), "{s}");
+                assert_eq!(r1.posix_captures(input), r2.posix_captures(input), "{s}");
+            }
+        }
+    }
+
+    #[test]
+    fn same_matches() {
+        let patterns = [
+            "(a|a|ab)(c|bcd|bce)(d*d*)",
+            "(a*a*)(a|b|ab)",
+            "((x?)*)(y|yz|yzz)",
+            "(if|in|int)(\\d+\\d*)?",
+        ];
+        let inputs: [&[u8]; 6] = [b"abcd", b"abcdd", b"aaab", b"xxyzz", b"int12", b"y"];
+        for s in patterns {
+            let config = Config {
+                auto_groups: true,
+                ..Config::default()
+            };
+            let ast = build_ast(Lexer::new(s.as_bytes(), config.clone())).unwrap();
+            let simple = ast.simplify();
+            assert!(simple.size() < ast.size(), "{s}");
+            let (r1, r2) = (
+                compile_ast(&ast, config.clone()).unwrap(),
+                compile_ast(&simple, config).unwrap(),
+            );
+            for input in inputs {
+                assert_eq!(r1.find(input), r2.find(input), "{s}");
+                assert_eq!(r1.captures(input), r2.captures(input), "{s}");
+                assert_eq!(r1.posix_captures(input), r2.posix_captures(input), "{s}");
+            }
+        }
+    }
+}
@@ -0,0 +1,319 @@
+/*
+ * Tagged DFA (Laurikari)
+ *
+ * - each group has two tags: 2g for its start and 2g + 1 for its end, set when a path passes
+ *   head or tail node of the group in nfa
+ * - a state is an ordered list of nfa nodes (configurations) and look behind,
+ *   each configuration has its own registers, that hold a position for each tag
+ * - configurations are ordered by priority: alternatives from left to right, repeats are greedy;
+ *   a node, reached by several paths, keeps the registers of the path with the highest priority
+ * - a transition copies registers of each new configuration from a configuration of the previous state,
+ *   and sets tags, passed in epsilon closure, to the current position
+ * - the match is the longest one, groups are read from the accepting configuration with the highest priority
+ */
+
+/// uncooked nfa with ordered edges, tags are set when passing its nodes
+struct TaggedNFA {
+    /// epsilon edges and assertions, in order of priority
+    eps: Vec<Vec<(usize, Option<Assert>)>>,
+    edges: Vec<Vec<(usize, Charset)>>,
+    tags: Vec<Vec<usize>>,
+    accept: Vec<bool>,
+}
+
+/// registers of a new configuration: (configuration of the previous state, tags set to the current position)
+pub type TagOp = (usize, Vec<usize>);
+
+pub struct TDFA {
+    pub classes: ByteClasses,
+    /// nodes[n][class] is the next state or DEAD
+    pub nodes: Vec<Vec<usize>>,
+    /// ops[n][class] defines registers for each configuration of the next state
+    pub ops: Vec<Vec<Vec<TagOp>>>,
+    /// start state for each look behind
+    pub start: Vec<usize>,
+    /// accept[n][look] is the accepting configuration with the highest priority, if any
+    pub accept: Vec<Vec<Option<TagOp>>>,
+    /// number of tags, twice the number of groups
+    pub tags: usize,
+}
+
+impl TaggedNFA {
+    fn new(nfa: &NFAUncooked) -> TaggedNFA {
+        let mut res = TaggedNFA {
+            eps: vec![vec![]; nfa.nodes],
+            edges: vec![vec![]; nfa.nodes],
+            tags: vec![vec![]; nfa.nodes],
+            accept: vec![false; nfa.nodes],
+        };
+        for (a, b) in nfa.eps_edges.iter() {
+            res.eps[*a].push((*b, None));
+        }
+        for (a, b, x) in nfa.asserts.iter() {
+            res.eps[*a].push((*b, Some(*x)));
+        }
+        for (a, b, s) in nfa.edges.iter() {
+            res.edges[*a].push((*b, s.clone()));
+        }
+        for (n, g) in nfa.head.iter() {
+            res.tags[*n].push(2 * g);
+        }
+        for (n, g) in nfa.tail.iter() {
+            res.tags[*n].push(2 * g + 1);
+        }
+        for n in nfa.accept.keys() {
+            res.accept[*n] = true;
+        }
+        res
+    }
+
+    /// configurations, reachable from `kernel` without reading a byte, in order of priority:
+    /// (nfa node, configuration of `kernel` it comes from, tags set on the way)
+    fn closure(&self, kernel: &[usize], prev: usize, next: usize) -> Vec<(usize, TagOp)> {
+        let mut used = vec![false; self.eps.len()];
+        let mut res = Vec::new();
+        for (i, n) in kernel.iter().enumerate() {
+            self.closure_dfs(&mut used, &mut res, (prev, next), *n, (i, vec![]));
+        }
+        res
+    }
+
+    fn closure_dfs(
+        &self,
+        used: &mut Vec<bool>,
+        res: &mut Vec<(usize, TagOp)>,
+        looks: (usize, usize),
+        n: usize,
+        (origin, mut tags): TagOp,
+    ) {
+        if used[n] {
+            return;
+        }
+        used[n] = true;
+        tags.extend(&self.tags[n]);
+        res.push((n, (origin, tags.clone())));
+        for (m, x) in self.eps[n].iter() {
+            if x.is_none_or(|x| x.check(looks.0, looks.1)) {
+                self.closure_dfs(used, res, looks, *m, (origin, tags.clone()));
+            }
+        }
+    }
+}
+
+pub fn build_tdfa(nfa: &NFAUncooked) -> TDFA {
+    build_tdfa_limited(nfa, usize::MAX).unwrap()
+}
+
+/// build tagged dfa, unless it gets more than `limit` states
+pub fn build_tdfa_limited(nfa: &NFAUncooked, limit: usize) -> Option<TDFA> {
+    let mut tdfa = TDFA::empty(nfa);
+    tdfa.init_from_nfa(nfa, limit).then_some(tdfa)
+}
+
+/// registers of a new configuration
+fn apply_tags(
+    regs: &[Vec<Option<usize>>],
+    (origin, tags): &TagOp,
+    pos: usize,
+) -> Vec<Option<usize>> {
+    let mut res = regs[*origin].clone();
+    for t in tags {
+        res[*t] = Some(pos);
+    }
+    res
+}
+
+/// group spans from registers of tags
+fn tag_spans(regs: &[Option<usize>]) -> Vec<Span> {
+    regs.chunks(2)
+        .map(|t| match (t[0], t[1]) {
+            (Some(a), Some(b)) => (Some(a), Some(b)),
+            _ => (None, None),
+        })
+        .collect()
+}
+
+impl TDFA {
+    /// tagged dfa without states
+    pub fn empty(nfa: &NFAUncooked) -> TDFA {
+        TDFA {
+            classes: nfa.classes(),
+            nodes: vec![],
+            ops: vec![],
+            start: vec![],
+            accept: vec![],
+            tags: 2 * nfa.groups,
+        }
+    }
+
+    /// find state by its kernel and look ahead, that leads to it, or create a new one
+    fn state(
+        &mut self,
+        nfa: &TaggedNFA,
+        subsets: &mut Subsets,
+        queue: &mut VecDeque<(usize, Vec<usize>, usize)>,
+        kernel: Vec<usize>,
+        look: usize,
+    ) -> usize {
+        if let Some(id) = subsets.get(&kernel, look) {
+            return id;
+        }
+        let id = subsets.insert(kernel.clone(), look);
+        let behind = subsets.behind(look);
+        self.nodes.push(vec![DEAD; self.classes.count]);
+        self.ops.push(vec![vec![]; self.classes.count]);
+        self.accept.push(
+            (0..LOOKS)
+                .map(|look| {
+                    let closure = nfa.closure(&kernel, behind, look);
+                    closure
+                        .into_iter()
+                        .find(|(n, _)| nfa.accept[*n])
+                        .map(|(_, op)| op)
+                })
+                .collect(),
+        );
+        queue.push_back((id, kernel, behind));
+        id
+    }
+
+    fn init_from_nfa(&mut self, uncooked: &NFAUncooked, limit: usize) -> bool {
+        let nfa = TaggedNFA::new(uncooked);
+        let mut subsets = Subsets::new(!uncooked.asserts.is_empty());
+        let mut queue: VecDeque<(usize, Vec<usize>, usize)> = VecDeque::new();
+        for look in 0..LOOKS {
+            let id = self.state(&nfa, &mut subsets, &mut queue, vec![uncooked.begin], look);
+            self.start.push(id);
+        }
+
+        let reps = self.classes.reps();
+        while let Some((id_from, kernel, prev)) = queue.pop_front() {
+            if self.nodes.len() > limit {
+                return false;
+            }
+            let from: Vec<Vec<(usize, TagOp)>> =
+                (0..LOOKS).map(|l| nfa.closure(&kernel, prev, l)).collect();
+            for (class, c) in reps.iter().enumerate() {
+                let look = self.classes.look[class];
+                let mut to: Vec<usize> = Vec::new();
+                let mut ops: Vec<TagOp> = Vec::new();
+                for (n, op) in from[look].iter() {
+                    for (m, s) in nfa.edges[*n].iter() {
+                        if s.contains(*c) && !to.contains(m) {
+                            to.push(*m);
+                            ops.push(op.clone());
+                        }
+                    }
+                }
+
+                if to.is_empty() {
+                    continue;
+                }
+
+                self.nodes[id_from][class] = self.state(&nfa, &mut subsets, &mut queue, to, look);
+                self.ops[id_from][class] = ops;
+            }
+        }
+        self.nodes.len() <= limit
+    }
+
+    /// spans of all groups for the longest match, that starts at s[start..]
+    pub fn captures_at(&self, s: &[u8], start: usize) -> Option<Vec<Span>> {
+        let mut state = self.start[self.classes.look(s[..start].last().copied())];
+        let mut regs: Vec<Vec<Option<usize>>> = vec![vec![None; self.tags]];
+        let mut res: Option<Vec<Option<usize>>> = None;
+        for pos in start..=s.len() {
+            let look = self.classes.look(s.get(pos).copied());
+            if let Some(op) = &self.accept[state][look] {
+                res = Some(apply_tags(&regs, op, pos));
+            }
+            let class = match s.get(pos) {
+                Some(c) => self.classes.get(*c),
+                None => break,
+            };
+            if self.nodes[state][class] == DEAD {
+                break;
+            }
+            regs = self.ops[state][class]
+                .iter()
+                .map(|op| apply_tags(&regs, op, pos))
+                .collect();
+            state = self.nodes[state][class];
+        }
+        res.map(|r| tag_spans(&r))
+    }
+}
+
+#[cfg(test)]
+mod test_tdfa {
+    use super::*;
+
+    fn tdfa(s: &[u8]) -> TDFA {
+        let config = Config {
+            auto_groups: true,
+            ..Config::default()
+        };
+        build_tdfa(&nfa_uncooked(Lexer::new(s, config)).unwrap())
+    }
+
+    #[test]
+    fn just_works() {
+        let t = tdfa(b"(a)|b");
+        assert_eq!(t.tags, 2);
+        assert_eq!(t.nodes.len(), 3);
+        let a = t.nodes[t.start[LOOK_EDGE]][t.classes.get(b'a')];
+        assert_eq!(
+            t.ops[t.start[LOOK_EDGE]][t.classes.get(b'a')],
+            vec![(0, vec![0])]
+        );
+        assert_eq!(t.accept[a][LOOK_EDGE], Some((0, vec![1])));
+        assert_eq!(t.captures_at(b"a", 0), Some(vec![(Some(0), Some(1))]));
+        assert_eq!(t.captures_at(b"b", 0), Some(vec![(None, None)]));
+        assert_eq!(t.captures_at(b"xab", 1), Some(vec![(Some(1), Some(2))]));
+        assert_eq!(t.captures_at(b"c", 0), None);
+    }
+
+    #[test]
+    fn priority() {
+        let t = tdfa(b"(a*)(a*)");
+        assert_eq!(
+            t.captures_at(b"aaa", 0),
+            Some(vec![(Some(0), Some(3)), (Some(3), Some(3))])
+        );
+        let t = tdfa(b"(a|ab)(c|bcd)?");
+        assert_eq!(
+            t.captures_at(b"abcd", 0),
+            Some(vec![(Some(0), Some(1)), (Some(1), Some(4))])
+        );
+        // last iteration wins
+        let t = tdfa(b"((a)|b)+");
+        assert_eq!(
+            t.captures_at(b"aab", 0),
+            Some(vec![(Some(2), Some(3)), (Some(1), Some(2))])
+        );
+        let t = tdfa(b"(a{2})(a{1,2})(a+)");
+        assert_eq!(
+            t.captures_at(b"aaaaa", 0),
+            Some(vec![
+                (Some(0), Some(2)),
+                (Some(2), Some(4)),
+                (Some(4), Some(5))
+            ])
+        );
+    }
+
+    #[test]
+    fn anchors() {
+        let t = tdfa(b"(a$|a)(b?)");
+        assert_eq!(
+            t.captures_at(b"a", 0),
+            Some(vec![(Some(0), Some(1)), (Some(1), Some(1))])
+        );
+        assert_eq!(
+            t.captures_at(b"ab", 0),
+            Some(vec![(Some(0), Some(1)), (Some(1), Some(2))])
+        );
+        let t = tdfa(b"(^a)");
+        assert_eq!(t.captures_at(b"aa", 1), None);
+    }
+}
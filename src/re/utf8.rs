@@ -0,0 +1,348 @@
+/*
+ * Unicode support
+ *
+ * - set of unicode scalar values is stored as sorted disjoint ranges
+ * - each range is split into sequences of utf-8 byte ranges, so that nfa and dfa remain byte-based
+ * - only valid utf-8 is produced: no surrogates, no overlong encodings
+ * - case folding uses simple (one to one) case mappings of std
+ */
+
+pub const MAX_SCALAR: u32 = 0x10FFFF;
+const SURROGATES: (u32, u32) = (0xD800, 0xDFFF);
+/// no chars above have case mappings
+const CASED_MAX: u32 = 0x1FFFF;
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct UnicodeSet {
+    ranges: Vec<(u32, u32)>,
+}
+
+impl UnicodeSet {
+    pub fn new() -> UnicodeSet {
+        UnicodeSet { ranges: Vec::new() }
+    }
+
+    /// bytes of `c` are code points U+0000-U+00FF
+    /// in utf-8 mode, only ascii part of `c` is used, and if `c` has all of \x80-\xff,
+    /// it matches all non-ascii chars (so '.' and inverted classes keep matching them)
+    pub fn from_charset(c: &Charset, utf8: bool) -> UnicodeSet {
+        let mut s = UnicodeSet::new();
+        for b in c.iter().filter(|b| !utf8 || *b < 0x80) {
+            s.add_char(b as u32);
+        }
+        if utf8 && (0x80..=0xff).all(|b| c.contains(b)) {
+            s.add_range(0x80, MAX_SCALAR);
+        }
+        s
+    }
+
+    pub fn ranges(&self) -> &[(u32, u32)] {
+        &self.ranges
+    }
+
+    pub fn add_range(&mut self, a: u32, b: u32) {
+        assert!(a <= b);
+        self.ranges.push((a, b));
+        self.normalize();
+    }
+
+    /// sort and merge ranges
+    fn normalize(&mut self) {
+        self.ranges.sort();
+        let mut merged: Vec<(u32, u32)> = Vec::new();
+        for (a, b) in self.ranges.iter() {
+            match merged.last_mut() {
+                Some(last) if *a <= last.1.saturating_add(1) => last.1 = last.1.max(*b),
+                _ => merged.push((*a, *b)),
+            }
+        }
+        self.ranges = merged;
+    }
+
+    pub fn add_char(&mut self, c: u32) {
+        self.add_range(c, c);
+    }
+
+    pub fn add(&mut self, s: &UnicodeSet) {
+        for (a, b) in s.ranges.iter() {
+            self.add_range(*a, *b);
+        }
+    }
+
+    /// add other cases of all chars, outside of utf-8 mode only ascii letters have cases
+    pub fn fold(&self, utf8: bool) -> UnicodeSet {
+        let max = if utf8 { CASED_MAX } else { 0x7F };
+        let mut res = self.clone();
+        for (a, b) in self.ranges.iter().filter(|r| r.0 <= max) {
+            for c in (*a..=(*b).min(max)).filter_map(char::from_u32) {
+                let (lower, upper) = (c.to_lowercase(), c.to_uppercase());
+                if lower.len() == 1 {
+                    res.ranges.extend(lower.map(|c| (c as u32, c as u32)));
+                }
+                if upper.len() == 1 {
+                    res.ranges.extend(upper.map(|c| (c as u32, c as u32)));
+                }
+            }
+        }
+        res.normalize();
+        res
+    }
+
+    /// complement within [0, max], surrogates are never included
+    pub fn inv(mut self, max: u32) -> UnicodeSet {
+        self.add_range(SURROGATES.0, SURROGATES.1);
+        let mut s = UnicodeSet::new();
+        let mut next = 0;
+        for (a, b) in self.ranges {
+            if next < a && next <= max {
+                s.add_range(next, (a - 1).min(max));
+            }
+            next = next.max(b + 1);
+        }
+        if next <= max {
+            s.add_range(next, max);
+        }
+        s
+    }
+
+    pub fn first(&self) -> Option<u32> {
+        self.ranges.first().map(|r| r.0)
+    }
+
+    pub fn last(&self) -> Option<u32> {
+        self.ranges.last().map(|r| r.1)
+    }
+
+    pub fn empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// set as a byte charset, None if it has code points above U+00FF
+    pub fn to_charset(&self) -> Option<Charset> {
+        let mut s = charset!();
+        for (a, b) in self.ranges.iter() {
+            if *b > 0xff {
+                return None;
+            }
+            s.add_range(*a as u8, *b as u8);
+        }
+        Some(s)
+    }
+
+    /// utf-8 encodings of all chars within the set, each sequence is a list of byte ranges
+    pub fn utf8_sequences(&self) -> Vec<Vec<(u8, u8)>> {
+        let mut res = Vec::new();
+        for (a, b) in self.ranges.iter() {
+            utf8_split(*a, (*b).min(MAX_SCALAR), &mut res);
+        }
+        res
+    }
+}
+
+fn encode(c: u32) -> Vec<u8> {
+    let mut buf = [0; 4];
+    Vec::from(char::from_u32(c).unwrap().encode_utf8(&mut buf).as_bytes())
+}
+
+/// split [lo, hi] into ranges, where all chars have the same encoded length,
+/// and differ only in bytes, where both ends are within the same continuation block
+fn utf8_split(lo: u32, hi: u32, out: &mut Vec<Vec<(u8, u8)>>) {
+    if lo > hi {
+        return;
+    }
+    if lo <= SURROGATES.1 && hi >= SURROGATES.0 {
+        if lo < SURROGATES.0 {
+            utf8_split(lo, SURROGATES.0 - 1, out);
+        }
+        utf8_split(lo.max(SURROGATES.1 + 1), hi, out);
+        return;
+    }
+    for max in [0x7F, 0x7FF, 0xFFFF] {
+        if lo <= max && max < hi {
+            utf8_split(lo, max, out);
+            utf8_split(max + 1, hi, out);
+            return;
+        }
+    }
+    for i in 1..4 {
+        let m = (1 << (6 * i)) - 1;
+        if lo & !m != hi & !m {
+            if lo & m != 0 {
+                utf8_split(lo, lo | m, out);
+                utf8_split((lo | m) + 1, hi, out);
+                return;
+            }
+            if hi & m != m {
+                utf8_split(lo, (hi & !m) - 1, out);
+                utf8_split(hi & !m, hi, out);
+                return;
+            }
+        }
+    }
+    out.push(encode(lo).into_iter().zip(encode(hi)).collect());
+}
+
+#[cfg(test)]
+mod test_utf8 {
+    use super::*;
+
+    fn set(r: &[(u32, u32)]) -> UnicodeSet {
+        let mut s = UnicodeSet::new();
+        for (a, b) in r {
+            s.add_range(*a, *b);
+        }
+        s
+    }
+
+    #[test]
+    fn ranges() {
+        let s = set(&[(10, 20), (0, 3), (4, 5), (15, 30), (40, 40)]);
+        assert_eq!(s.ranges(), &[(0, 5), (10, 30), (40, 40)]);
+        assert_eq!(s.first(), Some(0));
+        assert_eq!(s.last(), Some(40));
+        assert_eq!(s.clone().inv(255).ranges(), &[(6, 9), (31, 39), (41, 255)]);
+        assert_eq!(
+            s.inv(MAX_SCALAR).ranges(),
+            &[(6, 9), (31, 39), (41, 0xD7FF), (0xE000, MAX_SCALAR)]
+        );
+        assert_eq!(set(&[(0, 0x7F)]).inv(0x7F).ranges(), &[]);
+        assert_eq!(
+            set(&[(0x61, 0x62)]).to_charset(),
+            Some(charset!(b'a', b'b'))
+        );
+        assert_eq!(set(&[(0x61, 0x100)]).to_charset(), None);
+    }
+
+    #[test]
+    fn from_charset() {
+        let c = charset!(b'a', 0xe9);
+        assert_eq!(
+            UnicodeSet::from_charset(&c, false).ranges(),
+            &[(0x61, 0x61), (0xe9, 0xe9)]
+        );
+        assert_eq!(UnicodeSet::from_charset(&c, true).ranges(), &[(0x61, 0x61)]);
+        assert_eq!(
+            UnicodeSet::from_charset(&charset!(b'a').inv(), true).ranges(),
+            &[(0, 0x60), (0x62, MAX_SCALAR)]
+        );
+        assert_eq!(
+            UnicodeSet::from_charset(&c.inv(), true).ranges(),
+            &[(0, 0x60), (0x62, 0x7F)]
+        );
+    }
+
+    #[test]
+    fn fold() {
+        let s = set(&[
+            (b'a' as u32, b'c' as u32),
+            (b'X' as u32, b'X' as u32),
+            (0xE9, 0xE9),
+        ]);
+        assert_eq!(
+            s.fold(false).ranges(),
+            &[
+                (0x41, 0x43),
+                (0x58, 0x58),
+                (0x61, 0x63),
+                (0x78, 0x78),
+                (0xE9, 0xE9)
+            ]
+        );
+        assert_eq!(
+            s.fold(true).ranges(),
+            &[
+                (0x41, 0x43),
+                (0x58, 0x58),
+                (0x61, 0x63),
+                (0x78, 0x78),
+                (0xC9, 0xC9),
+                (0xE9, 0xE9)
+            ]
+        );
+        // ω Ω
+        assert_eq!(
+            set(&[(0x3C9, 0x3C9)]).fold(true).ranges(),
+            &[(0x3A9, 0x3A9), (0x3C9, 0x3C9)]
+        );
+        // ß has no single-char uppercase
+        assert_eq!(set(&[(0xDF, 0xDF)]).fold(true).ranges(), &[(0xDF, 0xDF)]);
+        assert_eq!(
+            set(&[(0, MAX_SCALAR)]).fold(true).ranges(),
+            &[(0, MAX_SCALAR)]
+        );
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(
+            set(&[(0x61, 0x7a)]).utf8_sequences(),
+            vec![vec![(0x61, 0x7a)]]
+        );
+        // α-ω
+        assert_eq!(
+            set(&[(0x3B1, 0x3C9)]).utf8_sequences(),
+            vec![
+                vec![(0xCE, 0xCE), (0xB1, 0xBF)],
+                vec![(0xCF, 0xCF), (0x80, 0x89)]
+            ]
+        );
+        assert_eq!(
+            set(&[(0, MAX_SCALAR)]).utf8_sequences(),
+            vec![
+                vec![(0x00, 0x7F)],
+                vec![(0xC2, 0xDF), (0x80, 0xBF)],
+                vec![(0xE0, 0xE0), (0xA0, 0xBF), (0x80, 0xBF)],
+                vec![(0xE1, 0xEC), (0x80, 0xBF), (0x80, 0xBF)],
+                vec![(0xED, 0xED), (0x80, 0x9F), (0x80, 0xBF)],
+                vec![(0xEE, 0xEF), (0x80, 0xBF), (0x80, 0xBF)],
+                vec![(0xF0, 0xF0), (0x90, 0xBF), (0x80, 0xBF), (0x80, 0xBF)],
+                vec![(0xF1, 0xF3), (0x80, 0xBF), (0x80, 0xBF), (0x80, 0xBF)],
+                vec![(0xF4, 0xF4), (0x80, 0x8F), (0x80, 0xBF), (0x80, 0xBF)],
+            ]
+        );
+    }
+
+    #[test]
+    fn valid_only() {
+        let seqs = set(&[(0, MAX_SCALAR)]).utf8_sequences();
+        let matches = |s: &[u8]| {
+            seqs.iter()
+                .any(|q| q.len() == s.len() && q.iter().zip(s).all(|((a, b), c)| a <= c && c <= b))
+        };
+        for c in ['a', 'é', 'ω', '€', '😀', '\u{10FFFF}'] {
+            let mut buf = [0; 4];
+            assert!(matches(c.encode_utf8(&mut buf).as_bytes()));
+        }
+        for s in [
+            &b"\xff"[..],
+            b"\xc0\x80",
+            b"\xed\xa0\x80",
+            b"\xf4\x90\x80\x80",
+            b"\x80",
+        ] {
+            assert!(!matches(s));
+        }
+    }
+
+    #[test]
+    fn regex() {
+        let config = Config {
+            utf8: true,
+            ..Config::default()
+        };
+        let r = compile("[α-ω]+.é\\u{1F600}?".as_bytes(), config.clone()).unwrap();
+        assert!(r.is_match("αβωxé".as_bytes()));
+        assert!(r.is_match("α€é😀".as_bytes()));
+        assert!(!r.is_match("αbé😀😀".as_bytes()));
+        assert!(!r.is_match(b"\xce\xb1\xffe\xcc\x81"));
+        assert!(!r.is_match("aé".as_bytes()));
+
+        let r = compile(b".", config).unwrap();
+        for s in ["a", "é", "€", "😀"] {
+            assert!(r.is_match(s.as_bytes()));
+        }
+        for s in [&b"\xff"[..], b"\xc0\x80", b"\xed\xa0\x80", b"\x80", b"ab"] {
+            assert!(!r.is_match(s));
+        }
+    }
+}
@@ -7,52 +7,38 @@ pub mod att_re_tests {
     include!("t.rs");
     use super::*;
 
-    type MatchRes = Vec<(Option<usize>, Option<usize>)>;
+    type MatchRes = Vec<re::Span>;
 
     #[derive(Debug)]
     pub struct Test<'a> {
         pub re: &'a [u8],
         pub s: &'a [u8],
-        pub groups: &'a [(Option<usize>, Option<usize>)],
+        pub groups: &'a [re::Span],
         pub err: bool,
+        /// groups follow posix rules, so they are computed and compared
+        pub posix: bool,
     }
 
-    fn match_compiled(r: &re::Regex, s: &[u8], start: usize, res: &MatchRes) -> bool {
-        let mut state = 0;
-        let mut failed = false;
-        for (i, c) in s
-            .iter()
-            .enumerate()
-            .skip(start)
-            .chain(std::iter::once((s.len(), &0)))
-        {
-            for (g, (h, t)) in res.iter().enumerate() {
-                if (*h == Some(i) || *t == Some(i)) && failed {
-                    return false;
-                }
-                if *h == Some(i) && !r.head[state].contains(&g) {
-                    return false;
-                }
-                if *t == Some(i) && !r.tail[state].contains(&g) {
-                    return false;
-                }
-            }
-            if i != s.len() {
-                let next = r.nodes[state].get(c);
-                match next {
-                    None => failed = true,
-                    Some(next) => state = *next,
-                }
-            }
-        }
-        return true;
+    fn verify_match(pattern: &[u8], s: &[u8], res: &MatchRes) -> re::Result<bool> {
+        let config = re::Config {
+            auto_groups: true,
+            ..re::Config::default()
+        };
+        let r = re::compile(pattern, config)?;
+        return Ok(r.check_captures(s, res));
     }
 
-    fn verify_match(pattern: &[u8], s: &[u8], res: &MatchRes) -> re::Result<bool> {
-        let mut config = re::Config::default();
-        config.auto_groups = true;
+    /// posix groups of the leftmost-longest match, groups omitted in `res` are not compared
+    fn compare_match(pattern: &[u8], s: &[u8], res: &MatchRes) -> re::Result<bool> {
+        let config = re::Config {
+            auto_groups: true,
+            ..re::Config::default()
+        };
         let r = re::compile(pattern, config)?;
-        return Ok(match_compiled(&r, s, res[0].0.unwrap(), res));
+        let groups = r
+            .find(s)
+            .and_then(|(start, _)| r.posix_captures_at(s, start));
+        Ok(groups.is_some_and(|g| g.len() >= res.len() && g[..res.len()] == res[..]))
     }
 
     #[test]
@@ -83,6 +69,24 @@ pub mod att_re_tests {
         );
     }
 
+    #[test]
+    fn compare_works() {
+        let groups = vec![(Some(1), Some(4)), (Some(1), Some(3)), (Some(3), Some(4))];
+        assert_eq!(
+            compare_match(b"((a|ab)(c|bcd)?)", b"xabc", &groups),
+            Ok(true)
+        );
+        assert_eq!(
+            compare_match(b"((a|ab)(c|bcd)?)", b"xabc", &groups[..1].to_vec()),
+            Ok(true)
+        );
+        let groups = vec![(Some(1), Some(4)), (Some(1), Some(2)), (Some(2), Some(4))];
+        assert_eq!(
+            compare_match(b"((a|ab)(c|bcd)?)", b"xabc", &groups),
+            Ok(false)
+        );
+    }
+
     // fn graphviz_dfa(s: &[u8]) -> Graphviz<re::DFA> {
     //     let mut c = re::Config::default();
     //     c.auto_groups = true;
@@ -99,7 +103,10 @@ pub mod att_re_tests {
                 std::str::from_utf8(t.s).unwrap_or("<?>"),
                 t.groups
             );
-            let res = verify_match(t.re, t.s, &Vec::from(t.groups));
+            let res = match t.posix {
+                true => compare_match(t.re, t.s, &Vec::from(t.groups)),
+                false => verify_match(t.re, t.s, &Vec::from(t.groups)),
+            };
             if res.is_err() {
                 eprintln!("compilation failed with '{}'", res.clone().unwrap_err());
             }